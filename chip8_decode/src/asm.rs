@@ -0,0 +1,667 @@
+//Assembler for Cowgod-style source, the same syntax `Syntax::Cowgod` prints.
+//
+//  ; comments run to the end of the line
+//  start:              ; a label, optionally followed by a statement on the same line
+//      LD V0, 0x05
+//      LD I, sprite
+//      DRW V1, V2, 5
+//      JP start + 2    ; operands are expressions over numbers, labels and $ (this line's address)
+//      org 0x300       ; continue assembling at 0x300, padding with zeros
+//  sprite:
+//      db 0xF0, 0x90, 0b11110000
+//      dw 0x1234       ; big endian, like opcodes
+//
+//Numbers are decimal, 0x hex or 0b binary. Expressions support + - * / % & | ^ << >> ~
+//and parentheses, with C precedence. Mnemonics, registers and directives are case
+//insensitive, and the register names (V0-VF, I, DT, ST, K, F, HF, B, R) can't be labels.
+//
+//Every instruction is built as an `Instr` and encoded with `Instr::to_bytes`, so the
+//output is exactly what `Instr::decode` reads back.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use shared::numtypes::{u12, u4};
+use shared::reg::GPReg;
+
+use crate::disasm::LOAD_ADDR;
+use crate::instructions::Instr;
+
+/// An assembled ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Program {
+    /// Address of the first byte
+    pub base: u16,
+    pub bytes: Vec<u8>,
+    pub labels: BTreeMap<String, u16>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum AsmErrorKind {
+    UnexpectedChar(char),
+    /// Expected one thing, found another (or the end of the line)
+    Expected { expected: &'static str, found: String },
+    UnknownMnemonic(String),
+    /// The mnemonic exists, but not with these operands
+    BadOperands(String),
+    UndefinedLabel(String),
+    DuplicateLabel(String),
+    /// A value doesn't fit in its operand
+    OutOfRange { value: i64, min: i64, max: i64 },
+    DivideByZero,
+    /// `org` below the load address or below what was already assembled
+    OrgBackwards { org: i64, current: u16 },
+    /// The program doesn't fit in the address space
+    Overflow,
+}
+
+/// An assembly error, with the 1-based line and column it was found at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+    pub line: usize,
+    pub col: usize,
+    pub kind: AsmErrorKind,
+}
+
+impl fmt::Display for AsmErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AsmErrorKind::UnexpectedChar(ch) => write!(f, "unexpected character '{ch}'"),
+            AsmErrorKind::Expected { expected, found } => write!(f, "expected {expected}, found {found}"),
+            AsmErrorKind::UnknownMnemonic(name) => write!(f, "unknown instruction \"{name}\""),
+            AsmErrorKind::BadOperands(name) => write!(f, "invalid operands for {name}"),
+            AsmErrorKind::UndefinedLabel(name) => write!(f, "undefined label \"{name}\""),
+            AsmErrorKind::DuplicateLabel(name) => write!(f, "label \"{name}\" is already defined"),
+            AsmErrorKind::OutOfRange { value, min, max } => write!(f, "value {value} is out of range ({min} to {max})"),
+            AsmErrorKind::DivideByZero => write!(f, "division by zero"),
+            AsmErrorKind::OrgBackwards { org, current } => write!(f, "org 0x{org:X} is before the current address 0x{current:03X}"),
+            AsmErrorKind::Overflow => write!(f, "program doesn't fit in memory"),
+        }
+    }
+}
+
+impl fmt::Display for AsmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}: {}", self.line, self.col, self.kind)
+    }
+}
+
+impl std::error::Error for AsmError {}
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+/// Assemble `src` into a ROM loaded at `LOAD_ADDR`
+pub fn assemble(src: &str) -> Result<Program> {
+    let mut stmts = Vec::new();
+    let mut labels = BTreeMap::new();
+    let mut addr = LOAD_ADDR;
+
+    //First pass: parse everything and assign addresses to labels
+    for (idx, text) in src.lines().enumerate() {
+        let line = idx + 1;
+        let err = |col, kind| AsmError { line, col, kind };
+        let tokens = lex(text).map_err(|(col, kind)| err(col, kind))?;
+        let mut p = Parser { tokens, pos: 0, end: text.chars().count() + 1 };
+
+        if let (Some((col, Tok::Ident(name))), Some((_, Tok::Sym(':')))) = (p.tokens.first(), p.tokens.get(1)) {
+            if is_reserved(name) {
+                return Err(err(*col, AsmErrorKind::Expected { expected: "a label", found: format!("register {name}") }));
+            }
+            if labels.insert(name.clone(), addr).is_some() {
+                return Err(err(*col, AsmErrorKind::DuplicateLabel(name.clone())));
+            }
+            p.pos = 2;
+        }
+
+        let Some(stmt) = p.statement().map_err(|(col, kind)| err(col, kind))? else {
+            continue;
+        };
+
+        match &stmt.kind {
+            StmtKind::Org(expr) => {
+                //Forward references can't work here, the label addresses aren't known yet
+                let org = expr.eval(&labels, addr).map_err(|(col, kind)| err(col, kind))?;
+                if org < addr as i64 || org > u16::MAX as i64 {
+                    return Err(err(stmt.col, AsmErrorKind::OrgBackwards { org, current: addr }));
+                }
+                addr = org as u16;
+            },
+            kind => {
+                addr = addr.checked_add(kind.size())
+                    .ok_or(err(stmt.col, AsmErrorKind::Overflow))?;
+            },
+        }
+        stmts.push((line, addr, stmt));
+    }
+
+    //Second pass: evaluate operands and emit bytes
+    let mut bytes = Vec::new();
+    for (line, end, stmt) in stmts {
+        let err = |(col, kind)| AsmError { line, col, kind };
+        let here = end - stmt.kind.size();
+        let start = (here - LOAD_ADDR) as usize;
+        bytes.resize(start, 0);
+
+        match stmt.kind {
+            StmtKind::Org(_) => {},
+            StmtKind::Db(exprs) => for expr in exprs {
+                bytes.push(expr.eval_in(&labels, here, -0x80, 0xFF).map_err(err)? as u8);
+            },
+            StmtKind::Dw(exprs) => for expr in exprs {
+                let word = expr.eval_in(&labels, here, -0x8000, 0xFFFF).map_err(err)? as u16;
+                bytes.extend_from_slice(&word.to_be_bytes());
+            },
+            StmtKind::Instr(mnemonic, operands) => {
+                let instr = build(&mnemonic, &operands, &labels, here)
+                    .map_err(err)?
+                    .ok_or(AsmError { line, col: stmt.col, kind: AsmErrorKind::BadOperands(mnemonic.to_uppercase()) })?;
+                bytes.extend(instr.to_bytes());
+            },
+        }
+    }
+
+    Ok(Program { base: LOAD_ADDR, bytes, labels })
+}
+
+//Column and error, before the line number is attached
+type LineError = (usize, AsmErrorKind);
+
+#[derive(Clone, Debug, PartialEq)]
+enum Tok {
+    Ident(String),
+    Num(i64),
+    Sym(char),
+    Shl,
+    Shr,
+}
+
+impl fmt::Display for Tok {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Tok::Ident(name) => write!(f, "\"{name}\""),
+            Tok::Num(num) => write!(f, "{num}"),
+            Tok::Sym(ch) => write!(f, "'{ch}'"),
+            Tok::Shl => write!(f, "'<<'"),
+            Tok::Shr => write!(f, "'>>'"),
+        }
+    }
+}
+
+fn lex(line: &str) -> std::result::Result<Vec<(usize, Tok)>, LineError> {
+    let chars: Vec<char> = line.chars().collect();
+    let mut tokens = Vec::new();
+    let mut pos = 0;
+
+    while pos < chars.len() {
+        let ch = chars[pos];
+        let col = pos + 1;
+        let word_end = |from: usize| (from..chars.len())
+            .find(|&idx| !(chars[idx].is_ascii_alphanumeric() || chars[idx] == '_'))
+            .unwrap_or(chars.len());
+
+        match ch {
+            ';' => break,
+            _ if ch.is_whitespace() => pos += 1,
+            _ if ch.is_ascii_alphabetic() || ch == '_' => {
+                let end = word_end(pos + 1);
+                tokens.push((col, Tok::Ident(chars[pos..end].iter().collect())));
+                pos = end;
+            },
+            _ if ch.is_ascii_digit() => {
+                let end = word_end(pos + 1);
+                let word: String = chars[pos..end].iter().collect();
+                let lower = word.to_lowercase();
+                let parsed = if let Some(hex) = lower.strip_prefix("0x") {
+                    i64::from_str_radix(hex, 16)
+                } else if let Some(bin) = lower.strip_prefix("0b") {
+                    i64::from_str_radix(bin, 2)
+                } else {
+                    lower.parse()
+                };
+                let num = parsed.map_err(|_| (col, AsmErrorKind::Expected { expected: "a number", found: format!("\"{word}\"") }))?;
+                tokens.push((col, Tok::Num(num)));
+                pos = end;
+            },
+            '<' | '>' if chars.get(pos + 1) == Some(&ch) => {
+                tokens.push((col, if ch == '<' { Tok::Shl } else { Tok::Shr }));
+                pos += 2;
+            },
+            ',' | ':' | '[' | ']' | '(' | ')' | '+' | '-' | '*' | '/' | '%' | '&' | '|' | '^' | '~' | '$' => {
+                tokens.push((col, Tok::Sym(ch)));
+                pos += 1;
+            },
+            _ => return Err((col, AsmErrorKind::UnexpectedChar(ch))),
+        }
+    }
+
+    Ok(tokens)
+}
+
+#[derive(Clone, Debug)]
+enum Expr {
+    Num(i64),
+    Label(String),
+    Here,
+    Neg(Box<Expr>),
+    Not(Box<Expr>),
+    Binary(char, Box<Expr>, Box<Expr>),
+}
+
+//An expression, with the column it starts at for error reporting
+#[derive(Clone, Debug)]
+struct Operand {
+    col: usize,
+    expr: Expr,
+}
+
+impl Expr {
+    fn eval(&self, labels: &BTreeMap<String, u16>, here: u16) -> std::result::Result<i64, AsmErrorKind> {
+        Ok(match self {
+            Expr::Num(num) => *num,
+            Expr::Label(name) => *labels.get(name).ok_or(AsmErrorKind::UndefinedLabel(name.clone()))? as i64,
+            Expr::Here => here as i64,
+            Expr::Neg(expr) => expr.eval(labels, here)?.wrapping_neg(),
+            Expr::Not(expr) => !expr.eval(labels, here)?,
+            Expr::Binary(op, lhs, rhs) => {
+                let (lhs, rhs) = (lhs.eval(labels, here)?, rhs.eval(labels, here)?);
+                match op {
+                    '+' => lhs.wrapping_add(rhs),
+                    '-' => lhs.wrapping_sub(rhs),
+                    '*' => lhs.wrapping_mul(rhs),
+                    '/' => lhs.checked_div(rhs).ok_or(AsmErrorKind::DivideByZero)?,
+                    '%' => lhs.checked_rem(rhs).ok_or(AsmErrorKind::DivideByZero)?,
+                    '&' => lhs & rhs,
+                    '|' => lhs | rhs,
+                    '^' => lhs ^ rhs,
+                    '<' => lhs.wrapping_shl(rhs as u32),
+                    '>' => lhs.wrapping_shr(rhs as u32),
+                    _ => unreachable!("parser only builds known operators"),
+                }
+            },
+        })
+    }
+}
+
+impl Operand {
+    fn eval(&self, labels: &BTreeMap<String, u16>, here: u16) -> std::result::Result<i64, LineError> {
+        self.expr.eval(labels, here).map_err(|kind| (self.col, kind))
+    }
+
+    fn eval_in(&self, labels: &BTreeMap<String, u16>, here: u16, min: i64, max: i64) -> std::result::Result<i64, LineError> {
+        let value = self.eval(labels, here)?;
+        if !(min..=max).contains(&value) {
+            return Err((self.col, AsmErrorKind::OutOfRange { value, min, max }));
+        }
+        Ok(value)
+    }
+}
+
+//Instruction operands
+#[derive(Clone, Debug)]
+enum Arg {
+    V(GPReg),
+    Range(GPReg, GPReg),
+    I,
+    IndirectI,
+    Dt,
+    St,
+    K,
+    F,
+    Hf,
+    B,
+    R,
+    Long(Operand),
+    Value(Operand),
+}
+
+#[derive(Clone, Debug)]
+enum StmtKind {
+    Org(Operand),
+    Db(Vec<Operand>),
+    Dw(Vec<Operand>),
+    Instr(String, Vec<Arg>),
+}
+
+impl StmtKind {
+    fn size(&self) -> u16 {
+        match self {
+            StmtKind::Org(_) => 0,
+            StmtKind::Db(exprs) => exprs.len() as u16,
+            StmtKind::Dw(exprs) => 2 * exprs.len() as u16,
+            StmtKind::Instr(_, args) if args.iter().any(|arg| matches!(arg, Arg::Long(_))) => 4,
+            StmtKind::Instr(..) => 2,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+struct Stmt {
+    col: usize,
+    kind: StmtKind,
+}
+
+fn register(name: &str) -> Option<GPReg> {
+    let mut chars = name.chars();
+    match (chars.next(), chars.next(), chars.next()) {
+        (Some('v' | 'V'), Some(idx), None) => GPReg::indexed(idx.to_digit(16)? as u8),
+        _ => None,
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    register(name).is_some() || ["i", "dt", "st", "k", "f", "hf", "b", "r", "long"].contains(&name.to_lowercase().as_str())
+}
+
+struct Parser {
+    tokens: Vec<(usize, Tok)>,
+    pos: usize,
+    //Column just past the end of the line
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Tok> {
+        self.tokens.get(self.pos).map(|(_, tok)| tok)
+    }
+
+    //Column of the next token, or just past the last one at the end of the line
+    fn col(&self) -> usize {
+        match self.tokens.get(self.pos) {
+            Some((col, _)) => *col,
+            None => self.end,
+        }
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.peek().cloned();
+        self.pos += 1;
+        tok
+    }
+
+    fn eat(&mut self, sym: char) -> bool {
+        if self.peek() == Some(&Tok::Sym(sym)) {
+            self.pos += 1;
+            return true;
+        }
+        false
+    }
+
+    fn expected<T>(&self, expected: &'static str) -> std::result::Result<T, LineError> {
+        let found = self.peek().map_or("end of line".into(), Tok::to_string);
+        Err((self.col(), AsmErrorKind::Expected { expected, found }))
+    }
+
+    fn statement(&mut self) -> std::result::Result<Option<Stmt>, LineError> {
+        let col = self.col();
+        let name = match self.next() {
+            None => return Ok(None),
+            Some(Tok::Ident(name)) => name.to_lowercase(),
+            Some(_) => {
+                self.pos -= 1;
+                return self.expected("an instruction or directive");
+            },
+        };
+
+        let kind = match name.as_str() {
+            "org" => StmtKind::Org(self.operand()?),
+            "db" => StmtKind::Db(self.list(Parser::operand)?),
+            "dw" => StmtKind::Dw(self.list(Parser::operand)?),
+            _ if is_mnemonic(&name) => StmtKind::Instr(name, self.list(Parser::arg)?),
+            _ => return Err((col, AsmErrorKind::UnknownMnemonic(name.to_uppercase()))),
+        };
+
+        if self.peek().is_some() {
+            return self.expected("end of line");
+        }
+        Ok(Some(Stmt { col, kind }))
+    }
+
+    //Comma separated items up to the end of the line
+    fn list<T>(&mut self, item: fn(&mut Parser) -> std::result::Result<T, LineError>) -> std::result::Result<Vec<T>, LineError> {
+        let mut items = Vec::new();
+        if self.peek().is_none() {
+            return Ok(items);
+        }
+
+        items.push(item(self)?);
+        while self.eat(',') {
+            items.push(item(self)?);
+        }
+        Ok(items)
+    }
+
+    fn arg(&mut self) -> std::result::Result<Arg, LineError> {
+        if self.eat('[') {
+            match self.next() {
+                Some(Tok::Ident(name)) if name.eq_ignore_ascii_case("i") => {},
+                _ => {
+                    self.pos -= 1;
+                    return self.expected("I");
+                },
+            }
+            if !self.eat(']') {
+                return self.expected("']'");
+            }
+            return Ok(Arg::IndirectI);
+        }
+
+        let Some(Tok::Ident(name)) = self.peek().cloned() else {
+            return Ok(Arg::Value(self.operand()?));
+        };
+
+        if let Some(reg) = register(&name) {
+            self.pos += 1;
+            //Vx - Vy is a range, registers never appear in expressions
+            if self.peek() == Some(&Tok::Sym('-')) {
+                if let Some(Tok::Ident(end)) = self.tokens.get(self.pos + 1).map(|(_, tok)| tok) {
+                    if let Some(end) = register(end) {
+                        self.pos += 2;
+                        return Ok(Arg::Range(reg, end));
+                    }
+                }
+            }
+            return Ok(Arg::V(reg));
+        }
+
+        let keyword = match name.to_lowercase().as_str() {
+            "i" => Arg::I,
+            "dt" => Arg::Dt,
+            "st" => Arg::St,
+            "k" => Arg::K,
+            "f" => Arg::F,
+            "hf" => Arg::Hf,
+            "b" => Arg::B,
+            "r" => Arg::R,
+            "long" => {
+                self.pos += 1;
+                return Ok(Arg::Long(self.operand()?));
+            },
+            _ => return Ok(Arg::Value(self.operand()?)),
+        };
+        self.pos += 1;
+        Ok(keyword)
+    }
+
+    fn operand(&mut self) -> std::result::Result<Operand, LineError> {
+        let col = self.col();
+        Ok(Operand { col, expr: self.binary(0)? })
+    }
+
+    //Precedence climbing, loosest binding first
+    fn binary(&mut self, level: usize) -> std::result::Result<Expr, LineError> {
+        const LEVELS: &[&[char]] = &[&['|'], &['^'], &['&'], &['<', '>'], &['+', '-'], &['*', '/', '%']];
+        if level == LEVELS.len() {
+            return self.unary();
+        }
+
+        let mut lhs = self.binary(level + 1)?;
+        loop {
+            let op = match self.peek() {
+                Some(Tok::Sym(op)) => *op,
+                Some(Tok::Shl) => '<',
+                Some(Tok::Shr) => '>',
+                _ => break,
+            };
+            if !LEVELS[level].contains(&op) {
+                break;
+            }
+            self.pos += 1;
+            let rhs = self.binary(level + 1)?;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(rhs));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> std::result::Result<Expr, LineError> {
+        if self.eat('-') {
+            return Ok(Expr::Neg(Box::new(self.unary()?)));
+        }
+        if self.eat('~') {
+            return Ok(Expr::Not(Box::new(self.unary()?)));
+        }
+        if self.eat('$') {
+            return Ok(Expr::Here);
+        }
+        if self.eat('(') {
+            let expr = self.binary(0)?;
+            if !self.eat(')') {
+                return self.expected("')'");
+            }
+            return Ok(expr);
+        }
+
+        match self.peek().cloned() {
+            Some(Tok::Num(num)) => {
+                self.pos += 1;
+                Ok(Expr::Num(num))
+            },
+            Some(Tok::Ident(name)) if !is_reserved(&name) => {
+                self.pos += 1;
+                Ok(Expr::Label(name))
+            },
+            _ => self.expected("an expression"),
+        }
+    }
+}
+
+fn is_mnemonic(name: &str) -> bool {
+    [
+        "sys", "cls", "ret", "jp", "call", "se", "sne", "ld", "add", "or", "and", "xor", "sub", "shr", "subn", "shl",
+        "rnd", "drw", "skp", "sknp", "scd", "scr", "scl", "exit", "low", "high", "scu", "plane", "audio", "pitch",
+    ].contains(&name)
+}
+
+//Build the instruction for a mnemonic and its operands. Ok(None) means the
+//operands don't fit the mnemonic.
+fn build(mnemonic: &str, args: &[Arg], labels: &BTreeMap<String, u16>, here: u16) -> std::result::Result<Option<Instr>, LineError> {
+    let addr = |op: &Operand| op.eval_in(labels, here, 0, 0xFFF).map(|val| u12::of(val as u16));
+    let byte = |op: &Operand| op.eval_in(labels, here, -0x80, 0xFF).map(|val| val as u8);
+    let nibble = |op: &Operand| op.eval_in(labels, here, 0, 0xF).map(|val| u4::of(val as u8));
+
+    use Arg::*;
+    use Instr::*;
+    Ok(Some(match (mnemonic, args) {
+        ("sys", [Value(a)]) => SYS(addr(a)?),
+        ("cls", []) => CLS,
+        ("ret", []) => RET,
+        ("jp", [Value(a)]) => JP(addr(a)?),
+        ("jp", [V(GPReg::V0), Value(a)]) => JPL(addr(a)?),
+        ("call", [Value(a)]) => CALL(addr(a)?),
+        ("se", [V(x), Value(kk)]) => SEQ(*x, byte(kk)?),
+        ("se", [V(x), V(y)]) => SE(*x, *y),
+        ("sne", [V(x), Value(kk)]) => SNELIT(*x, byte(kk)?),
+        ("sne", [V(x), V(y)]) => SNE(*x, *y),
+        ("ld", [V(x), Value(kk)]) => LDL(*x, byte(kk)?),
+        ("ld", [V(x), V(y)]) => LD(*x, *y),
+        ("ld", [I, Value(a)]) => LDI(addr(a)?),
+        ("ld", [I, Long(a)]) => LDIL(a.eval_in(labels, here, 0, 0xFFFF)? as u16),
+        ("ld", [V(x), Dt]) => MOVDT(*x),
+        ("ld", [V(x), K]) => LDKB(*x),
+        ("ld", [Dt, V(x)]) => LDDT(*x),
+        ("ld", [St, V(x)]) => LDST(*x),
+        ("ld", [F, V(x)]) => LDSPR(*x),
+        ("ld", [Hf, V(x)]) => LDHSPR(*x),
+        ("ld", [B, V(x)]) => LDBCD(*x),
+        ("ld", [IndirectI, V(x)]) => PUSHREG(*x),
+        ("ld", [V(x), IndirectI]) => POPREG(*x),
+        ("ld", [IndirectI, Range(x, y)]) => PUSHRANGE(*x, *y),
+        ("ld", [Range(x, y), IndirectI]) => POPRANGE(*x, *y),
+        ("ld", [R, V(x)]) => PUSHRPL(*x),
+        ("ld", [V(x), R]) => POPRPL(*x),
+        ("add", [V(x), Value(kk)]) => ADDL(*x, byte(kk)?),
+        ("add", [V(x), V(y)]) => ADDC(*x, *y),
+        ("add", [I, V(x)]) => ADDI(*x),
+        ("or", [V(x), V(y)]) => OR(*x, *y),
+        ("and", [V(x), V(y)]) => AND(*x, *y),
+        ("xor", [V(x), V(y)]) => XOR(*x, *y),
+        ("sub", [V(x), V(y)]) => SUBC(*x, *y),
+        ("subn", [V(x), V(y)]) => SUBN(*x, *y),
+        //Cowgod writes SHR Vx {, Vy}. Without Vy, shift Vx in place whatever the shifting quirk.
+        ("shr", [V(x)]) => SHRC(*x, *x),
+        ("shr", [V(x), V(y)]) => SHRC(*x, *y),
+        ("shl", [V(x)]) => SHLC(*x, *x),
+        ("shl", [V(x), V(y)]) => SHLC(*x, *y),
+        ("rnd", [V(x), Value(kk)]) => RND(*x, byte(kk)?),
+        ("drw", [V(x), V(y), Value(n)]) => DRW(*x, *y, nibble(n)?),
+        ("skp", [V(x)]) => SKP(*x),
+        ("sknp", [V(x)]) => SKNP(*x),
+        ("scd", [Value(n)]) => SCD(nibble(n)?),
+        ("scr", []) => SCR,
+        ("scl", []) => SCL,
+        ("exit", []) => EXIT,
+        ("low", []) => LOW,
+        ("high", []) => HIGH,
+        ("scu", [Value(n)]) => SCU(nibble(n)?),
+        ("plane", [Value(n)]) => PLANE(nibble(n)?),
+        ("audio", []) => AUDIO,
+        ("pitch", [V(x)]) => PITCH(*x),
+        _ => return Ok(None),
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::disasm::Disassembly;
+    use crate::syntax::{Formatter, Syntax};
+
+    #[test]
+    fn assembles() {
+        let src = "
+            start:  LD V0, 0x05         ; comment
+                    ld i, sprite
+                    DRW V1, V2, 5
+                    LD [I], V0 - V3
+                    LD I, LONG sprite + 1
+                    ADD V0, -1
+                    JP start + (2 * 1)
+                    org 0x210
+            sprite: db 0xF0, 0b1001, 1
+                    dw $
+        ";
+        let program = assemble(src).unwrap();
+        assert_eq!(program.labels["sprite"], 0x210);
+        assert_eq!(program.bytes, [
+            0x60, 0x05, 0xA2, 0x10, 0xD1, 0x25, 0x50, 0x32, 0xF0, 0x00, 0x02, 0x11, 0x70, 0xFF, 0x12, 0x02,
+            0xF0, 0x09, 0x01, 0x02, 0x13,
+        ]);
+    }
+
+    #[test]
+    fn reassembles_disassembly() {
+        let rom = [0x22, 0x06, 0xA2, 0x0A, 0xB3, 0x00, 0x3A, 0x01, 0x00, 0xEE, 0xF0, 0x90, 0x7F];
+        let listing = Disassembly::new(&rom).listing(&Formatter { addresses: false, opcodes: false, syntax: Syntax::Cowgod });
+        assert_eq!(assemble(&listing).unwrap().bytes, rom);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src| assemble(src).unwrap_err();
+        assert_eq!(error("CLS\n  LD V0, V1, V2"), AsmError { line: 2, col: 3, kind: AsmErrorKind::BadOperands("LD".into()) });
+        assert_eq!(error("  JP nowhere"), AsmError { line: 1, col: 6, kind: AsmErrorKind::UndefinedLabel("nowhere".into()) });
+        assert_eq!(error("LD V0, 256").kind, AsmErrorKind::OutOfRange { value: 256, min: -0x80, max: 0xFF });
+        assert_eq!(error("LD V0, @").kind, AsmErrorKind::UnexpectedChar('@'));
+        assert_eq!(error("a: CLS\na: CLS").kind, AsmErrorKind::DuplicateLabel("a".into()));
+        assert!(matches!(error("LD V0,").kind, AsmErrorKind::Expected { .. }));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,309 @@
+//Control flow graph of a disassembled ROM.
+//
+//Blocks start at the entry point, at every jump and call target, and after every
+//instruction that doesn't simply fall through. A block ending in a skip has an edge for
+//each outcome. A call has an edge to the subroutine and a fallthrough edge to the
+//return site, and every RET reachable from the subroutine gets a return edge back to
+//each of its call sites.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+
+use crate::disasm::Disassembly;
+use crate::flow::Flow;
+use crate::instructions::Instr;
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum EdgeKind {
+    /// Execution continues into the next block, including after a call returns
+    Fallthrough,
+    Jump,
+    /// A skip whose condition held
+    SkipTaken,
+    /// A skip whose condition didn't hold
+    SkipNotTaken,
+    Call,
+    Return,
+}
+
+impl EdgeKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            EdgeKind::Fallthrough => "fallthrough",
+            EdgeKind::Jump => "jump",
+            EdgeKind::SkipTaken => "skip_taken",
+            EdgeKind::SkipNotTaken => "skip_not_taken",
+            EdgeKind::Call => "call",
+            EdgeKind::Return => "return",
+        }
+    }
+}
+
+/// An edge between the blocks starting at `from` and `to`
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct Edge {
+    pub from: u16,
+    pub to: u16,
+    pub kind: EdgeKind,
+}
+
+/// A run of instructions that is only entered at the top and only left at the bottom
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: u16,
+    /// One past the last byte of the block
+    pub end: u16,
+    pub instrs: Vec<(u16, Instr)>,
+}
+
+impl BasicBlock {
+    pub fn last(&self) -> (u16, Instr) {
+        *self.instrs.last().expect("blocks are never empty")
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct Cfg {
+    entry: u16,
+    blocks: BTreeMap<u16, BasicBlock>,
+    edges: Vec<Edge>,
+    unresolved: Vec<u16>,
+}
+
+impl Cfg {
+    pub fn new(dis: &Disassembly) -> Self {
+        let mut leaders = BTreeSet::from([dis.base()]);
+        for (addr, instr) in dis.instrs() {
+            let next = addr.wrapping_add(instr.byte_len());
+            match instr.flow() {
+                Flow::Next => {},
+                Flow::Jump(target) | Flow::Call(target) => {
+                    leaders.insert(target);
+                    leaders.insert(next);
+                },
+                Flow::Skip => {
+                    leaders.insert(next);
+                    leaders.insert(next.wrapping_add(dis.skip_len(next)));
+                },
+                Flow::JumpIndexed(_) | Flow::Return | Flow::Exit => {
+                    leaders.insert(next);
+                },
+            }
+        }
+
+        //Split the instructions into blocks at leaders, gaps and control flow
+        let mut blocks: BTreeMap<u16, BasicBlock> = BTreeMap::new();
+        let mut current: Option<BasicBlock> = None;
+        for (addr, instr) in dis.instrs() {
+            let continues = current.as_ref().is_some_and(|block| block.end == addr && !leaders.contains(&addr));
+            if !continues {
+                if let Some(block) = current.take() {
+                    blocks.insert(block.start, block);
+                }
+            }
+
+            let block = current.get_or_insert_with(|| BasicBlock { start: addr, end: addr, instrs: Vec::new() });
+            block.instrs.push((addr, *instr));
+            block.end = addr.wrapping_add(instr.byte_len());
+
+            if instr.flow() != Flow::Next {
+                blocks.insert(block.start, current.take().expect("block was just inserted"));
+            }
+        }
+        if let Some(block) = current {
+            blocks.insert(block.start, block);
+        }
+
+        let mut cfg = Cfg { entry: dis.base(), blocks, edges: Vec::new(), unresolved: dis.unresolved().collect() };
+        cfg.add_edges(dis);
+        cfg
+    }
+
+    pub fn entry(&self) -> u16 {
+        self.entry
+    }
+
+    pub fn blocks(&self) -> impl Iterator<Item = &BasicBlock> + '_ {
+        self.blocks.values()
+    }
+
+    pub fn block(&self, start: u16) -> Option<&BasicBlock> {
+        self.blocks.get(&start)
+    }
+
+    pub fn edges(&self) -> &[Edge] {
+        &self.edges
+    }
+
+    /// Addresses of `JP V0, addr` instructions, whose outgoing edges are unknown
+    pub fn unresolved(&self) -> &[u16] {
+        &self.unresolved
+    }
+
+    fn add_edges(&mut self, dis: &Disassembly) {
+        let mut edges = Vec::new();
+        let mut edge = |from: u16, to: u16, kind| {
+            if self.blocks.contains_key(&to) {
+                edges.push(Edge { from, to, kind });
+            }
+        };
+
+        for block in self.blocks.values() {
+            let (addr, instr) = block.last();
+            let next = addr.wrapping_add(instr.byte_len());
+            match instr.flow() {
+                Flow::Next => edge(block.start, next, EdgeKind::Fallthrough),
+                Flow::Jump(target) => edge(block.start, target, EdgeKind::Jump),
+                Flow::Call(target) => {
+                    edge(block.start, target, EdgeKind::Call);
+                    edge(block.start, next, EdgeKind::Fallthrough);
+                },
+                Flow::Skip => {
+                    edge(block.start, next, EdgeKind::SkipNotTaken);
+                    edge(block.start, next.wrapping_add(dis.skip_len(next)), EdgeKind::SkipTaken);
+                },
+                Flow::JumpIndexed(_) | Flow::Return | Flow::Exit => {},
+            }
+        }
+        self.edges = edges;
+
+        //Return edges: from each RET a subroutine can reach, back to each of its return sites
+        let mut returns = Vec::new();
+        for call in self.edges.iter().filter(|edge| edge.kind == EdgeKind::Call) {
+            let (addr, instr) = self.blocks[&call.from].last();
+            let site = addr.wrapping_add(instr.byte_len());
+            if !self.blocks.contains_key(&site) {
+                continue;
+            }
+
+            for ret in self.subroutine_returns(call.to) {
+                returns.push(Edge { from: ret, to: site, kind: EdgeKind::Return });
+            }
+        }
+        returns.sort_by_key(|edge| (edge.from, edge.to));
+        returns.dedup();
+        self.edges.extend(returns);
+    }
+
+    //Blocks ending in RET that are reachable from `start` without leaving the subroutine
+    fn subroutine_returns(&self, start: u16) -> Vec<u16> {
+        let mut seen = BTreeSet::new();
+        let mut pending = vec![start];
+        let mut returns = Vec::new();
+
+        while let Some(block) = pending.pop() {
+            if !seen.insert(block) {
+                continue;
+            }
+            if self.blocks[&block].last().1.flow() == Flow::Return {
+                returns.push(block);
+            }
+            pending.extend(self.edges.iter()
+                .filter(|edge| edge.from == block && edge.kind != EdgeKind::Call)
+                .map(|edge| edge.to));
+        }
+
+        returns
+    }
+
+    /// Graphviz DOT, one node per block labelled with its instructions
+    pub fn to_dot(&self) -> String {
+        let mut out = String::new();
+        let _ = writeln!(out, "digraph cfg {{");
+        let _ = writeln!(out, "    node [shape=box, fontname=\"monospace\"];");
+
+        for block in self.blocks.values() {
+            let mut label = String::new();
+            for (addr, instr) in &block.instrs {
+                let _ = write!(label, "0x{addr:03X}  {instr}\\l");
+            }
+            let entry = if block.start == self.entry { ", penwidth=2" } else { "" };
+            let unresolved = if self.unresolved.contains(&block.last().0) { ", color=red" } else { "" };
+            let _ = writeln!(out, "    b{:03X} [label=\"{label}\"{entry}{unresolved}];", block.start);
+        }
+
+        for edge in &self.edges {
+            let style = match edge.kind {
+                EdgeKind::Fallthrough | EdgeKind::Jump => "",
+                EdgeKind::SkipTaken => ", color=darkgreen",
+                EdgeKind::SkipNotTaken => ", color=red",
+                EdgeKind::Call => ", style=dashed",
+                EdgeKind::Return => ", style=dotted",
+            };
+            let _ = writeln!(out, "    b{:03X} -> b{:03X} [label=\"{}\"{style}];", edge.from, edge.to, edge.kind.name());
+        }
+
+        out.push_str("}\n");
+        out
+    }
+
+    /// JSON with the entry point, the blocks and their instructions, the edges
+    /// and the unresolved jumps. Addresses are numbers.
+    pub fn to_json(&self) -> String {
+        let blocks: Vec<String> = self.blocks.values()
+            .map(|block| {
+                let instrs: Vec<String> = block.instrs.iter()
+                    .map(|(addr, instr)| {
+                        let opcode: String = instr.to_bytes().iter().map(|byte| format!("{byte:02X}")).collect();
+                        format!("{{\"addr\":{addr},\"opcode\":\"{opcode}\",\"text\":\"{}\"}}", json_escape(&instr.to_string()))
+                    })
+                    .collect();
+                format!("{{\"start\":{},\"end\":{},\"instrs\":[{}]}}", block.start, block.end, instrs.join(","))
+            })
+            .collect();
+        let edges: Vec<String> = self.edges.iter()
+            .map(|edge| format!("{{\"from\":{},\"to\":{},\"kind\":\"{}\"}}", edge.from, edge.to, edge.kind.name()))
+            .collect();
+        let unresolved: Vec<String> = self.unresolved.iter().map(u16::to_string).collect();
+
+        format!(
+            "{{\"entry\":{},\"blocks\":[{}],\"edges\":[{}],\"unresolved\":[{}]}}",
+            self.entry, blocks.join(","), edges.join(","), unresolved.join(","),
+        )
+    }
+}
+
+fn json_escape(text: &str) -> String {
+    text.chars()
+        .flat_map(|ch| match ch {
+            '"' | '\\' => vec!['\\', ch],
+            _ => vec![ch],
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocks_and_edges() {
+        let rom = [
+            0x22, 0x08, //0x200 CALL 0x208
+            0x12, 0x00, //0x202 JP 0x200
+            0x00, 0x00, //0x204
+            0x00, 0x00, //0x206
+            0x3A, 0x01, //0x208 SE VA, 0x01
+            0x00, 0xE0, //0x20A CLS
+            0x00, 0xEE, //0x20C RET
+        ];
+        let cfg = Cfg::new(&Disassembly::new(&rom));
+
+        let starts: Vec<u16> = cfg.blocks().map(|block| block.start).collect();
+        assert_eq!(starts, [0x200, 0x202, 0x208, 0x20A, 0x20C]);
+
+        let edge = |from, to, kind| Edge { from, to, kind };
+        assert_eq!(cfg.edges(), [
+            edge(0x200, 0x208, EdgeKind::Call),
+            edge(0x200, 0x202, EdgeKind::Fallthrough),
+            edge(0x202, 0x200, EdgeKind::Jump),
+            edge(0x208, 0x20A, EdgeKind::SkipNotTaken),
+            edge(0x208, 0x20C, EdgeKind::SkipTaken),
+            edge(0x20A, 0x20C, EdgeKind::Fallthrough),
+            edge(0x20C, 0x202, EdgeKind::Return),
+        ]);
+
+        assert!(cfg.to_dot().contains("b20C -> b202 [label=\"return\", style=dotted];"));
+        assert!(cfg.to_json().starts_with("{\"entry\":512,\"blocks\":[{\"start\":512,\"end\":514,\"instrs\":[{\"addr\":512,\"opcode\":\"2208\",\"text\":\"CALL 0x208\"}]}"));
+    }
+}
\ No newline at end of file
@@ -0,0 +1,223 @@
+//Reference opcode table, transcribed from section 3 of
+//http://devernay.free.fr/hacks/chip8/C8TECH10.HTM (including the Super Chip-48
+//instructions in 3.2), plus the XO-CHIP additions from
+//https://johnearnest.github.io/Octo/docs/XO-ChipSpecification.html
+//
+//The table is deliberately independent of `Instr::decode`: operands are pulled out
+//with shifts and masks, and patterns are matched most-specific first, so a mistake
+//in either one shows up as a mismatch instead of cancelling out.
+
+use std::fmt;
+use std::mem::discriminant;
+
+use shared::numtypes::{u12, u4};
+use shared::reg::GPReg;
+
+use crate::instructions::{DecodeErr, Instr};
+use crate::errors::Error;
+
+/// Operands of an opcode, extracted the way the spec describes them
+#[derive(Copy, Clone, Debug)]
+pub struct Operands {
+    pub nnn: u12,
+    pub n: u4,
+    pub x: GPReg,
+    pub y: GPReg,
+    pub kk: u8,
+}
+
+impl Operands {
+    pub fn of(opcode: u16) -> Self {
+        let reg = |idx: u16| GPReg::indexed((idx & 0xF) as u8).expect("a nibble is always a register");
+        Operands {
+            nnn: u12::of(opcode & 0xFFF),
+            n: u4::of((opcode & 0xF) as u8),
+            x: reg(opcode >> 8),
+            y: reg(opcode >> 4),
+            kk: (opcode & 0xFF) as u8,
+        }
+    }
+}
+
+/// One row of the reference table
+pub struct OpcodeSpec {
+    /// Opcode pattern as written in the spec, e.g. "8xy4". Hex digits are fixed,
+    /// letters are operands.
+    pub pattern: &'static str,
+    pub mnemonic: &'static str,
+    pub build: fn(Operands) -> Instr,
+}
+
+impl OpcodeSpec {
+    pub fn matches(&self, opcode: u16) -> bool {
+        self.pattern.chars()
+            .zip([12, 8, 4, 0])
+            .all(|(ch, shift)| match ch.to_digit(16) {
+                Some(digit) => (opcode >> shift) & 0xF == digit as u16,
+                None => true,
+            })
+    }
+
+    //Number of fixed nibbles, used to pick the most specific pattern
+    fn specificity(&self) -> usize {
+        self.pattern.chars()
+            .filter(|ch| ch.is_ascii_hexdigit())
+            .count()
+    }
+}
+
+macro_rules! spec {
+    ($pattern:literal, $mnemonic:literal, |$ops:ident| $build:expr) => {
+        OpcodeSpec { pattern: $pattern, mnemonic: $mnemonic, build: |$ops| $build }
+    };
+}
+
+pub static REFERENCE: &[OpcodeSpec] = &[
+    spec!("0nnn", "SYS addr", |o| Instr::SYS(o.nnn)),
+    spec!("00E0", "CLS", |_o| Instr::CLS),
+    spec!("00EE", "RET", |_o| Instr::RET),
+    spec!("1nnn", "JP addr", |o| Instr::JP(o.nnn)),
+    spec!("2nnn", "CALL addr", |o| Instr::CALL(o.nnn)),
+    spec!("3xkk", "SE Vx, byte", |o| Instr::SEQ(o.x, o.kk)),
+    spec!("4xkk", "SNE Vx, byte", |o| Instr::SNELIT(o.x, o.kk)),
+    spec!("5xy0", "SE Vx, Vy", |o| Instr::SE(o.x, o.y)),
+    spec!("6xkk", "LD Vx, byte", |o| Instr::LDL(o.x, o.kk)),
+    spec!("7xkk", "ADD Vx, byte", |o| Instr::ADDL(o.x, o.kk)),
+    spec!("8xy0", "LD Vx, Vy", |o| Instr::LD(o.x, o.y)),
+    spec!("8xy1", "OR Vx, Vy", |o| Instr::OR(o.x, o.y)),
+    spec!("8xy2", "AND Vx, Vy", |o| Instr::AND(o.x, o.y)),
+    spec!("8xy3", "XOR Vx, Vy", |o| Instr::XOR(o.x, o.y)),
+    spec!("8xy4", "ADD Vx, Vy", |o| Instr::ADDC(o.x, o.y)),
+    spec!("8xy5", "SUB Vx, Vy", |o| Instr::SUBC(o.x, o.y)),
+    spec!("8xy6", "SHR Vx {, Vy}", |o| Instr::SHRC(o.x, o.y)),
+    spec!("8xy7", "SUBN Vx, Vy", |o| Instr::SUBN(o.x, o.y)),
+    spec!("8xyE", "SHL Vx {, Vy}", |o| Instr::SHLC(o.x, o.y)),
+    spec!("9xy0", "SNE Vx, Vy", |o| Instr::SNE(o.x, o.y)),
+    spec!("Annn", "LD I, addr", |o| Instr::LDI(o.nnn)),
+    spec!("Bnnn", "JP V0, addr", |o| Instr::JPL(o.nnn)),
+    spec!("Cxkk", "RND Vx, byte", |o| Instr::RND(o.x, o.kk)),
+    spec!("Dxyn", "DRW Vx, Vy, nibble", |o| Instr::DRW(o.x, o.y, o.n)),
+    spec!("Ex9E", "SKP Vx", |o| Instr::SKP(o.x)),
+    spec!("ExA1", "SKNP Vx", |o| Instr::SKNP(o.x)),
+    spec!("Fx07", "LD Vx, DT", |o| Instr::MOVDT(o.x)),
+    spec!("Fx0A", "LD Vx, K", |o| Instr::LDKB(o.x)),
+    spec!("Fx15", "LD DT, Vx", |o| Instr::LDDT(o.x)),
+    spec!("Fx18", "LD ST, Vx", |o| Instr::LDST(o.x)),
+    spec!("Fx1E", "ADD I, Vx", |o| Instr::ADDI(o.x)),
+    spec!("Fx29", "LD F, Vx", |o| Instr::LDSPR(o.x)),
+    spec!("Fx33", "LD B, Vx", |o| Instr::LDBCD(o.x)),
+    spec!("Fx55", "LD [I], Vx", |o| Instr::PUSHREG(o.x)),
+    spec!("Fx65", "LD Vx, [I]", |o| Instr::POPREG(o.x)),
+    //Super Chip-48
+    spec!("00Cn", "SCD nibble", |o| Instr::SCD(o.n)),
+    spec!("00FB", "SCR", |_o| Instr::SCR),
+    spec!("00FC", "SCL", |_o| Instr::SCL),
+    spec!("00FD", "EXIT", |_o| Instr::EXIT),
+    spec!("00FE", "LOW", |_o| Instr::LOW),
+    spec!("00FF", "HIGH", |_o| Instr::HIGH),
+    spec!("Fx30", "LD HF, Vx", |o| Instr::LDHSPR(o.x)),
+    spec!("Fx75", "LD R, Vx", |o| Instr::PUSHRPL(o.x)),
+    spec!("Fx85", "LD Vx, R", |o| Instr::POPRPL(o.x)),
+    //XO-CHIP
+    spec!("00Dn", "SCU nibble", |o| Instr::SCU(o.n)),
+    spec!("5xy2", "LD [I], Vx - Vy", |o| Instr::PUSHRANGE(o.x, o.y)),
+    spec!("5xy3", "LD Vx - Vy, [I]", |o| Instr::POPRANGE(o.x, o.y)),
+    spec!("F000", "LD I, long addr", |_o| Instr::LDIL(0)),
+    spec!("Fn01", "PLANE n", |o| Instr::PLANE(u4::of(o.x.to_idx() as u8))),
+    spec!("F002", "AUDIO", |_o| Instr::AUDIO),
+    spec!("Fx3A", "PITCH Vx", |o| Instr::PITCH(o.x)),
+];
+
+/// The reference row for `opcode`, if any. When several patterns match,
+/// the one with the most fixed nibbles wins (00E0 over 0nnn).
+pub fn lookup(opcode: u16) -> Option<&'static OpcodeSpec> {
+    REFERENCE.iter()
+        .filter(|spec| spec.matches(opcode))
+        .max_by_key(|spec| spec.specificity())
+}
+
+/// A disagreement between `Instr::decode` and the reference table
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mismatch {
+    /// One side accepted the opcode and the other rejected it
+    Acceptance { opcode: u16, expected: Option<Instr>, decoded: Option<Instr> },
+    /// Both accepted it, but as different instructions
+    Variant { opcode: u16, expected: Instr, decoded: Instr },
+    /// Same instruction, different operands
+    Operands { opcode: u16, expected: Instr, decoded: Instr },
+    /// The decoded instruction doesn't encode back to the opcode it came from
+    Roundtrip { opcode: u16, decoded: Instr, encoded: u16 },
+}
+
+impl fmt::Display for Mismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Mismatch::Acceptance { opcode, expected, decoded } => write!(f, "0x{opcode:04X}: expected {expected:?}, decoded {decoded:?}"),
+            Mismatch::Variant { opcode, expected, decoded } => write!(f, "0x{opcode:04X}: expected {expected:?}, decoded a different instruction {decoded:?}"),
+            Mismatch::Operands { opcode, expected, decoded } => write!(f, "0x{opcode:04X}: expected {expected:?}, decoded wrong operands {decoded:?}"),
+            Mismatch::Roundtrip { opcode, decoded, encoded } => write!(f, "0x{opcode:04X}: {decoded:?} encodes back to 0x{encoded:04X}"),
+        }
+    }
+}
+
+/// Check a single opcode against the reference table
+pub fn check(opcode: u16) -> Result<(), Mismatch> {
+    let spec = lookup(opcode);
+
+    //F000 is the head of a 4-byte instruction, which only decode_long accepts
+    if spec.is_some_and(|spec| spec.pattern == "F000") {
+        return match Instr::decode(opcode) {
+            Err(Error::InstrErr(DecodeErr::Long(_))) => Ok(()),
+            other => Err(Mismatch::Acceptance { opcode, expected: None, decoded: other.ok() }),
+        };
+    }
+
+    let expected = spec.map(|spec| (spec.build)(Operands::of(opcode)));
+    let decoded = Instr::decode(opcode).ok();
+
+    let (expected, decoded) = match (expected, decoded) {
+        (None, None) => return Ok(()),
+        (Some(expected), Some(decoded)) => (expected, decoded),
+        (expected, decoded) => return Err(Mismatch::Acceptance { opcode, expected, decoded }),
+    };
+
+    if discriminant(&expected) != discriminant(&decoded) {
+        return Err(Mismatch::Variant { opcode, expected, decoded });
+    }
+
+    if expected != decoded {
+        return Err(Mismatch::Operands { opcode, expected, decoded });
+    }
+
+    let encoded = decoded.encode();
+    if encoded != opcode || Instr::decode(encoded).ok() != Some(decoded) {
+        return Err(Mismatch::Roundtrip { opcode, decoded, encoded });
+    }
+
+    Ok(())
+}
+
+/// Check every 16-bit opcode, returning all mismatches
+pub fn check_all() -> Vec<Mismatch> {
+    (0..=u16::MAX)
+        .filter_map(|opcode| check(opcode).err())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn all_opcodes_match_reference() {
+        let mismatches = check_all();
+        let report: Vec<String> = mismatches.iter().take(20).map(Mismatch::to_string).collect();
+        assert!(mismatches.is_empty(), "{} opcodes disagree with the reference table:\n{}", mismatches.len(), report.join("\n"));
+    }
+
+    #[test]
+    fn long_ldi() {
+        assert_eq!(Instr::decode_long(0xF000, 0x1234).ok(), Some(Instr::LDIL(0x1234)));
+        assert_eq!(Instr::LDIL(0x1234).to_bytes(), [0xF0, 0x00, 0x12, 0x34]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,323 @@
+//Recursive traversal disassembler.
+//
+//Starting from the entry point, every reachable instruction is decoded by following
+//fallthrough, JP, CALL and both outcomes of skips. RET and EXIT end a path. Anything
+//never reached is treated as data, and addresses loaded into I are labelled as data
+//so sprites don't show up as garbage instructions. JP V0 depends on a register, so
+//its targets can't be followed and the instruction is flagged as unresolved instead.
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use crate::errors::Error;
+use crate::flow::Flow;
+use crate::instructions::{DecodeErr, Instr};
+use crate::syntax::Formatter;
+
+/// Where ROMs are loaded, and where execution starts
+pub const LOAD_ADDR: u16 = 0x200;
+
+//Widest data line, so the raw bytes still fit the opcode column
+const DATA_PER_LINE: usize = 4;
+
+/// Why an address was given a label
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LabelKind {
+    /// A `CALL` target
+    Subroutine,
+    /// A `JP` target
+    Jump,
+    /// Loaded into I by `LD I, addr`
+    Data,
+}
+
+/// A label generated for an address in the ROM
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Label {
+    pub kind: LabelKind,
+    pub name: String,
+}
+
+/// One line of a listing
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Item {
+    Code(Instr),
+    Data(Vec<u8>),
+}
+
+/// The result of disassembling a ROM
+#[derive(Clone, Debug)]
+pub struct Disassembly {
+    base: u16,
+    rom: Vec<u8>,
+    code: BTreeMap<u16, Instr>,
+    labels: BTreeMap<u16, Label>,
+    unresolved: BTreeSet<u16>,
+}
+
+impl Disassembly {
+    /// Disassemble `rom`, loaded and entered at `LOAD_ADDR`
+    pub fn new(rom: &[u8]) -> Self {
+        Disassembly::with_base(rom, LOAD_ADDR)
+    }
+
+    /// Disassemble `rom` loaded at `base`, starting execution at `base`
+    pub fn with_base(rom: &[u8], base: u16) -> Self {
+        let mut dis = Disassembly {
+            base,
+            rom: rom.to_vec(),
+            code: BTreeMap::new(),
+            labels: BTreeMap::new(),
+            unresolved: BTreeSet::new(),
+        };
+        dis.trace(base);
+        dis.prune_labels();
+        dis
+    }
+
+    pub fn base(&self) -> u16 {
+        self.base
+    }
+
+    /// One past the last address of the ROM. This is 0x10000 for a ROM that fills
+    /// XO-CHIP's address space, so it doesn't fit a u16.
+    pub fn end(&self) -> u32 {
+        self.base as u32 + self.rom.len() as u32
+    }
+
+    /// The instruction starting at `addr`, if it was reached
+    pub fn instr_at(&self, addr: u16) -> Option<&Instr> {
+        self.code.get(&addr)
+    }
+
+    /// Every reached instruction, in address order
+    pub fn instrs(&self) -> impl Iterator<Item = (u16, &Instr)> + '_ {
+        self.code.iter().map(|(&addr, instr)| (addr, instr))
+    }
+
+    pub fn label(&self, addr: u16) -> Option<&Label> {
+        self.labels.get(&addr)
+    }
+
+    pub fn labels(&self) -> impl Iterator<Item = (u16, &Label)> + '_ {
+        self.labels.iter().map(|(&addr, label)| (addr, label))
+    }
+
+    /// Addresses of `JP V0, addr` instructions whose targets couldn't be followed
+    pub fn unresolved(&self) -> impl Iterator<Item = u16> + '_ {
+        self.unresolved.iter().copied()
+    }
+
+    /// Split the ROM into lines of code and data, with the address each starts at.
+    /// Data runs are broken at labels so every label starts a line.
+    pub fn items(&self) -> Vec<(u16, Item)> {
+        let mut items = Vec::new();
+        let mut addr = self.base as u32;
+
+        while addr < self.end() {
+            if let Some(instr) = self.code.get(&(addr as u16)) {
+                items.push((addr as u16, Item::Code(*instr)));
+                addr += instr.byte_len() as u32;
+                continue;
+            }
+
+            let start = addr as u16;
+            let mut bytes = Vec::new();
+            while addr < self.end() && bytes.len() < DATA_PER_LINE && !self.code.contains_key(&(addr as u16)) {
+                if addr as u16 != start && self.labels.contains_key(&(addr as u16)) {
+                    break;
+                }
+                bytes.push(self.byte(addr as u16));
+                addr += 1;
+            }
+            items.push((start, Item::Data(bytes)));
+        }
+
+        items
+    }
+
+    /// Render a listing with labels, using `formatter` for each line
+    pub fn listing(&self, formatter: &Formatter) -> String {
+        let syntax = formatter.syntax;
+        let mut out = String::new();
+
+        for (addr, item) in self.items() {
+            if let Some(label) = self.labels.get(&addr) {
+                out.push_str(&syntax.label(&label.name));
+                out.push('\n');
+            }
+
+            let line = match item {
+                Item::Code(instr) => {
+                    let label = instr.address()
+                        .and_then(|target| self.labels.get(&target))
+                        .map(|label| label.name.as_str());
+                    let mut line = formatter.instr_labeled(addr, &instr, label);
+                    if self.unresolved.contains(&addr) {
+                        line.push_str("  ");
+                        line.push_str(&syntax.comment("unresolved jump"));
+                    }
+                    line
+                },
+                Item::Data(bytes) => formatter.data(addr, &bytes),
+            };
+            out.push_str(&line);
+            out.push('\n');
+        }
+
+        out
+    }
+
+    fn contains(&self, addr: u16) -> bool {
+        (self.base as u32..self.end()).contains(&(addr as u32))
+    }
+
+    fn byte(&self, addr: u16) -> u8 {
+        self.rom[(addr - self.base) as usize]
+    }
+
+    fn word(&self, addr: u16) -> Option<u16> {
+        let lo = addr.checked_add(1).filter(|&lo| self.contains(addr) && self.contains(lo))?;
+        Some((self.byte(addr) as u16) << 8 | self.byte(lo) as u16)
+    }
+
+    fn decode_at(&self, addr: u16) -> Option<Instr> {
+        let opcode = self.word(addr)?;
+        match Instr::decode(opcode) {
+            Err(Error::InstrErr(DecodeErr::Long(_))) => {
+                let next = self.word(addr.checked_add(2)?)?;
+                Instr::decode_long(opcode, next).ok()
+            },
+            decoded => decoded.ok(),
+        }
+    }
+
+    /// How far a skip jumps when it skips the instruction at `addr`, see `Flow::skip_len`
+    pub fn skip_len(&self, addr: u16) -> u16 {
+        Flow::skip_len(self.word(addr))
+    }
+
+    fn add_label(&mut self, addr: u16, kind: LabelKind) {
+        if !self.contains(addr) {
+            return;
+        }
+
+        //A subroutine is more interesting than a jump target, which is more interesting than data
+        let kind = match self.labels.get(&addr) {
+            Some(label) => label.kind.min(kind),
+            None => kind,
+        };
+        let prefix = match kind {
+            LabelKind::Jump => "label",
+            LabelKind::Subroutine => "sub",
+            LabelKind::Data => "data",
+        };
+        self.labels.insert(addr, Label { kind, name: format!("{prefix}_{addr:03X}") });
+    }
+
+    fn trace(&mut self, entry: u16) {
+        let mut pending = vec![entry];
+
+        while let Some(addr) = pending.pop() {
+            if self.code.contains_key(&addr) {
+                continue;
+            }
+            let Some(instr) = self.decode_at(addr) else {
+                continue;
+            };
+            self.code.insert(addr, instr);
+
+            let next = addr.wrapping_add(instr.byte_len());
+            match instr.flow() {
+                Flow::Return | Flow::Exit => {},
+                Flow::Jump(target) => {
+                    self.add_label(target, LabelKind::Jump);
+                    pending.push(target);
+                },
+                Flow::Call(target) => {
+                    self.add_label(target, LabelKind::Subroutine);
+                    pending.push(target);
+                    pending.push(next);
+                },
+                Flow::JumpIndexed(_) => {
+                    self.unresolved.insert(addr);
+                },
+                Flow::Skip => {
+                    pending.push(next);
+                    pending.push(next.wrapping_add(self.skip_len(next)));
+                },
+                Flow::Next => pending.push(next),
+            }
+
+            if let (Instr::LDI(_) | Instr::LDIL(_), Some(data)) = (instr, instr.address()) {
+                self.add_label(data, LabelKind::Data);
+            }
+        }
+    }
+
+    //Labels are only useful where a line starts, so drop the ones pointing into the
+    //middle of an instruction
+    fn prune_labels(&mut self) {
+        let starts: BTreeSet<u16> = self.items().into_iter().map(|(addr, _)| addr).collect();
+        self.labels.retain(|addr, _| starts.contains(addr));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::syntax::Syntax;
+
+    #[test]
+    fn separates_code_from_data() {
+        let rom = [
+            0x22, 0x08, //0x200 CALL sub_208
+            0xA2, 0x0E, //0x202 LD I, data_20E
+            0xB3, 0x00, //0x204 JP V0, 0x300
+            0x12, 0x00, //0x206 never reached
+            0x3A, 0x01, //0x208 SE VA, 0x01
+            0x00, 0xE0, //0x20A CLS
+            0x00, 0xEE, //0x20C RET
+            0xF0, 0x90, 0x90, 0xF0, //0x20E sprite
+            0x7F, //0x212 odd trailing byte
+        ];
+        let dis = Disassembly::new(&rom);
+
+        assert_eq!(dis.instrs().map(|(addr, _)| addr).collect::<Vec<_>>(), [0x200, 0x202, 0x204, 0x208, 0x20A, 0x20C]);
+        assert_eq!(dis.unresolved().collect::<Vec<_>>(), [0x204]);
+        assert_eq!(dis.label(0x208).map(|l| l.kind), Some(LabelKind::Subroutine));
+        assert_eq!(dis.label(0x20E).map(|l| l.kind), Some(LabelKind::Data));
+
+        let listing = dis.listing(&Formatter { addresses: false, opcodes: false, syntax: Syntax::Cowgod });
+        assert_eq!(listing.lines().collect::<Vec<_>>(), [
+            "CALL sub_208",
+            "LD I, data_20E",
+            "JP V0, 0x300  ; unresolved jump",
+            "DB 0x12, 0x00",
+            "sub_208:",
+            "SE VA, 0x01",
+            "CLS",
+            "RET",
+            "data_20E:",
+            "DB 0xF0, 0x90, 0x90, 0xF0",
+            "DB 0x7F",
+        ]);
+    }
+
+    #[test]
+    fn fills_address_space() {
+        //JP 0x200, then data up to the last byte of XO-CHIP's 64K
+        let mut rom = vec![0x00; 0x10000 - LOAD_ADDR as usize];
+        rom[..2].copy_from_slice(&[0x12, 0x00]);
+        *rom.last_mut().unwrap() = 0x7F;
+        let dis = Disassembly::new(&rom);
+        assert_eq!(dis.end(), 0x10000);
+
+        let items = dis.items();
+        let len: usize = items.iter().map(|(_, item)| match item {
+            Item::Code(instr) => instr.byte_len() as usize,
+            Item::Data(bytes) => bytes.len(),
+        }).sum();
+        assert_eq!(len, rom.len());
+        assert_eq!(items.last(), Some(&(0xFFFE, Item::Data(vec![0x00, 0x7F]))));
+    }
+}
\ No newline at end of file
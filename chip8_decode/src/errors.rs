@@ -1,8 +1,109 @@
-use crate::instructions::DecodeErr;
+use std::fmt;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
-#[derive(Copy, Clone, Debug)]
+/// Why a word didn't decode. Both variants keep the full raw opcode.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DecodeErr {
+    /// No instruction in the opcode's group (its top nibble) matches the rest of it, e.g. 8xyF
+    Undefined(u16),
+    /// The opcode is the first half of a 4-byte instruction, see `Instr::decode_long`
+    Long(u16),
+}
+
+impl DecodeErr {
+    pub fn opcode(&self) -> u16 {
+        match *self {
+            DecodeErr::Undefined(opcode) | DecodeErr::Long(opcode) => opcode,
+        }
+    }
+
+    /// The encodings that would have been accepted in place of the opcode
+    pub fn expected(&self) -> &'static str {
+        match *self {
+            DecodeErr::Long(_) => "F000 followed by a 16-bit address",
+            DecodeErr::Undefined(opcode) => match opcode >> 12 {
+                0x5 => "5xy0, 5xy2 or 5xy3",
+                0x8 => "8xy0 to 8xy7 or 8xyE",
+                0x9 => "9xy0",
+                0xE => "Ex9E or ExA1",
+                0xF => "Fx07, Fx0A, Fx15, Fx18, Fx1E, Fx29, Fx30, Fx33, Fx3A, Fx55, Fx65, Fx75, Fx85, Fn01, F002 or F000 nnnn",
+                _ => "a defined opcode",
+            },
+        }
+    }
+}
+
+impl fmt::Display for DecodeErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeErr::Undefined(opcode) => write!(f, "undefined opcode 0x{opcode:04X}, expected {}", self.expected()),
+            DecodeErr::Long(opcode) => write!(f, "0x{opcode:04X} starts a 4-byte instruction, expected {}", self.expected()),
+        }
+    }
+}
+
+impl std::error::Error for DecodeErr {}
+
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Error {
     InstrErr(DecodeErr),
+    /// A decode error for the word fetched from `addr`, see `Error::at`
+    At { addr: u16, err: DecodeErr },
+}
+
+impl Error {
+    /// Attach the address the opcode was fetched from
+    pub fn at(self, addr: u16) -> Self {
+        Error::At { addr, err: self.decode_err() }
+    }
+
+    pub fn addr(&self) -> Option<u16> {
+        match *self {
+            Error::InstrErr(_) => None,
+            Error::At { addr, .. } => Some(addr),
+        }
+    }
+
+    pub fn decode_err(&self) -> DecodeErr {
+        match *self {
+            Error::InstrErr(err) | Error::At { err, .. } => err,
+        }
+    }
+
+    /// The raw word that failed to decode
+    pub fn opcode(&self) -> u16 {
+        self.decode_err().opcode()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InstrErr(err) => write!(f, "{err}"),
+            Error::At { addr, err } => write!(f, "{err} at 0x{addr:04X}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::InstrErr(err) | Error::At { err, .. } => Some(err),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::instructions::Instr;
+
+    #[test]
+    fn display() {
+        let err = Instr::decode(0x812F).unwrap_err();
+        assert_eq!(err.opcode(), 0x812F);
+        assert_eq!(err.to_string(), "undefined opcode 0x812F, expected 8xy0 to 8xy7 or 8xyE");
+        assert_eq!(err.at(0x204).to_string(), "undefined opcode 0x812F, expected 8xy0 to 8xy7 or 8xyE at 0x0204");
+        assert_eq!(err.at(0x204).addr(), Some(0x204));
+    }
 }
\ No newline at end of file
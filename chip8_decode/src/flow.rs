@@ -0,0 +1,93 @@
+use crate::instructions::Instr;
+
+/// How an instruction passes control on
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Flow {
+    /// Continues with the following instruction
+    Next,
+    /// Conditionally skips the following instruction
+    Skip,
+    /// Jumps to the address
+    Jump(u16),
+    /// Jumps to the address plus a register (JP V0, addr), so the target isn't known statically
+    JumpIndexed(u16),
+    /// Calls the subroutine at the address, which returns to the following instruction
+    Call(u16),
+    /// Returns to the caller
+    Return,
+    /// Stops the interpreter (SUPER-CHIP EXIT)
+    Exit,
+}
+
+impl Flow {
+    /// How many bytes a skip steps over when the next instruction starts with `opcode`:
+    /// all 4 of XO-CHIP's `F000 nnnn`, 2 for anything else
+    pub fn skip_len(opcode: Option<u16>) -> u16 {
+        if opcode == Some(0xF000) { 4 } else { 2 }
+    }
+}
+
+impl Instr {
+    pub fn flow(&self) -> Flow {
+        use Instr::*;
+        match *self {
+            JP(addr) => Flow::Jump(*addr),
+            JPL(addr) => Flow::JumpIndexed(*addr),
+            CALL(addr) => Flow::Call(*addr),
+            RET => Flow::Return,
+            EXIT => Flow::Exit,
+            SEQ(..) | SNELIT(..) | SE(..) | SNE(..) | SKP(_) | SKNP(_) => Flow::Skip,
+            _ => Flow::Next,
+        }
+    }
+
+    pub fn is_skip(&self) -> bool {
+        self.flow() == Flow::Skip
+    }
+
+    /// JP, or JP V0 whose target isn't known statically
+    pub fn is_jump(&self) -> bool {
+        matches!(self.flow(), Flow::Jump(_) | Flow::JumpIndexed(_))
+    }
+
+    pub fn is_call(&self) -> bool {
+        matches!(self.flow(), Flow::Call(_))
+    }
+
+    pub fn is_return(&self) -> bool {
+        self.flow() == Flow::Return
+    }
+
+    /// Addresses that may execute next when this instruction is at `pc`, and the
+    /// instruction after it starts with `next_opcode`. A call's successor is the
+    /// subroutine. Returns and JP V0 have none, since their targets depend on the
+    /// stack or a register.
+    pub fn successors(&self, pc: u16, next_opcode: Option<u16>) -> Vec<u16> {
+        let next = pc.wrapping_add(self.byte_len());
+        match self.flow() {
+            Flow::Next => vec![next],
+            Flow::Skip => vec![next, next.wrapping_add(Flow::skip_len(next_opcode))],
+            Flow::Jump(target) | Flow::Call(target) => vec![target],
+            Flow::JumpIndexed(_) | Flow::Return | Flow::Exit => vec![],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn successors() {
+        let decode = |opcode| Instr::decode(opcode).unwrap();
+        assert_eq!(decode(0x6005).successors(0x200, None), [0x202]);
+        assert_eq!(decode(0x3A01).successors(0x200, Some(0x6005)), [0x202, 0x204]);
+        assert_eq!(decode(0x2300).successors(0x200, None), [0x300]);
+        assert!(decode(0x00EE).successors(0x200, None).is_empty());
+        assert!(decode(0xB300).is_jump() && decode(0xB300).successors(0x200, None).is_empty());
+        assert_eq!(Instr::LDIL(0x1234).successors(0x200, None), [0x204]);
+
+        //A skip steps over the whole of F000 nnnn
+        assert_eq!(decode(0x3A01).successors(0x200, Some(0xF000)), [0x202, 0x206]);
+    }
+}
\ No newline at end of file
@@ -2,6 +2,8 @@
 
 use crate::Result;
 use crate::errors::Error;
+pub use crate::errors::DecodeErr;
+use crate::options::{DecodeOptions, Undefined};
 
 use shared::numtypes::*;
 use shared::reg::GPReg;
@@ -109,6 +111,7 @@ pub enum Instr {
     /// Dxyn - DRW Vx, Vy, nibble  
     /// Display n-byte sprite starting at memory location I at (Vx, Vy), set VF = collision.  
     /// The interpreter reads n bytes from memory, starting at the address stored in I. These bytes are then displayed as sprites on screen at coordinates (Vx, Vy). Sprites are XORed onto the existing screen. If this causes any pixels to be erased, VF is set to 1, otherwise it is set to 0. If the sprite is positioned so part of it is outside the coordinates of the display, it wraps around to the opposite side of the screen. See instruction 8xy3 for more information on XOR, and section 2.4, Display, for more information on the Chip-8 screen and sprites.  
+    /// On SUPER-CHIP, Dxy0 displays a 16x16 sprite made of 32 bytes (two bytes per row).  
     DRW(GPReg, GPReg, u4),
     /// Ex9E - SKP Vx  
     /// Skip next instruction if key with the value of Vx is pressed.  
@@ -154,14 +157,73 @@ pub enum Instr {
     /// Read registers V0 through Vx from memory starting at location I.  
     /// The interpreter reads values from memory starting at location I into registers V0 through Vx.  
     POPREG(GPReg),
-}
-
-#[derive(Copy, Clone, Debug)]
-pub enum DecodeErr {
-    Opcode(u16),
-    Subcode(u8, u8),
-    Reg(u16, u8),
-
+    /// 00Cn - SCD nibble  
+    /// Scroll the display down n pixels.  
+    /// SUPER-CHIP only. Rows scrolled in from the top are cleared.  
+    SCD(u4),
+    /// 00FB - SCR  
+    /// Scroll the display right 4 pixels.  
+    /// SUPER-CHIP only. Columns scrolled in from the left are cleared.  
+    SCR,
+    /// 00FC - SCL  
+    /// Scroll the display left 4 pixels.  
+    /// SUPER-CHIP only. Columns scrolled in from the right are cleared.  
+    SCL,
+    /// 00FD - EXIT  
+    /// Exit the interpreter.  
+    /// SUPER-CHIP only.  
+    EXIT,
+    /// 00FE - LOW  
+    /// Disable high resolution graphics mode.  
+    /// SUPER-CHIP only. The display returns to 64x32.  
+    LOW,
+    /// 00FF - HIGH  
+    /// Enable high resolution graphics mode.  
+    /// SUPER-CHIP only. The display becomes 128x64.  
+    HIGH,
+    /// Fx30 - LD HF, Vx  
+    /// Set I = location of large sprite for digit Vx.  
+    /// SUPER-CHIP only. Like Fx29, but points at the 8x10 hexadecimal font.  
+    LDHSPR(GPReg),
+    /// Fx75 - LD R, Vx  
+    /// Store registers V0 through Vx in the RPL user flags.  
+    /// SUPER-CHIP only.  
+    PUSHRPL(GPReg),
+    /// Fx85 - LD Vx, R  
+    /// Read registers V0 through Vx from the RPL user flags.  
+    /// SUPER-CHIP only.  
+    POPRPL(GPReg),
+    /// 00Dn - SCU nibble  
+    /// Scroll the display up n pixels.  
+    /// XO-CHIP only. Rows scrolled in from the bottom are cleared.  
+    SCU(u4),
+    /// 5xy2 - LD \[I], Vx - Vy  
+    /// Store registers Vx through Vy in memory starting at location I.  
+    /// XO-CHIP only. The range may be given in either order and I is not modified.  
+    PUSHRANGE(GPReg, GPReg),
+    /// 5xy3 - LD Vx - Vy, \[I]  
+    /// Read registers Vx through Vy from memory starting at location I.  
+    /// XO-CHIP only. The range may be given in either order and I is not modified.  
+    POPRANGE(GPReg, GPReg),
+    /// F000 nnnn - LD I, long addr  
+    /// Set I = nnnn.  
+    /// XO-CHIP only. This is the only 4-byte instruction; the address is the 16-bit word following the opcode.  
+    LDIL(u16),
+    /// Fn01 - PLANE n  
+    /// Select the drawing planes given by the bitmask n.  
+    /// XO-CHIP only. CLS, DRW and the scroll instructions only affect the selected planes.  
+    PLANE(u4),
+    /// F002 - AUDIO  
+    /// Load the 16-byte audio pattern buffer from memory starting at location I.  
+    /// XO-CHIP only.  
+    AUDIO,
+    /// Fx3A - PITCH Vx  
+    /// Set the audio playback pitch = Vx.  
+    /// XO-CHIP only.  
+    PITCH(GPReg),
+    /// An undefined opcode, decoded leniently. See `DecodeOptions`.  
+    /// Does nothing. Holds the raw opcode so it encodes back unchanged.  
+    NOP(u16),
 }
 
 impl Instr {
@@ -170,22 +232,31 @@ impl Instr {
 
         let byte = |hi, lo| hi << 4 | lo;
         let addr = u12::from_nibbles;
-        let gpreg = |idx| GPReg::indexed(idx).ok_or(Error::InstrErr(DecodeErr::Reg(value, idx)));
+        let gpreg = |idx| GPReg::indexed(idx).expect("a nibble is always a register");
 
         Ok(match nibbles {
             [0x0, 0x0, 0xE, 0x0] => Instr::CLS,
             [0x0, 0x0, 0xE, 0xE] => Instr::RET,
+            [0x0, 0x0, 0xC, n] => Instr::SCD(u4::of(n)),
+            [0x0, 0x0, 0xF, 0xB] => Instr::SCR,
+            [0x0, 0x0, 0xF, 0xC] => Instr::SCL,
+            [0x0, 0x0, 0xF, 0xD] => Instr::EXIT,
+            [0x0, 0x0, 0xF, 0xE] => Instr::LOW,
+            [0x0, 0x0, 0xF, 0xF] => Instr::HIGH,
+            [0x0, 0x0, 0xD, n] => Instr::SCU(u4::of(n)),
             [0x0, hi, mid, lo]=> Instr::SYS(addr(hi, mid, lo)),
             [0x1, hi, mid, lo] => Instr::JP(addr(hi, mid, lo)),
             [0x2, hi, mid, lo] => Instr::CALL(addr(hi, mid, lo)),
-            [0x3, reg, hi, lo] => Instr::SEQ(gpreg(reg)?, byte(hi, lo)),
-            [0x4, reg, hi, lo] => Instr::SNELIT(gpreg(reg)?, byte(hi, lo)),
-            [0x5, reg1, reg2, 0x0] => Instr::SE(gpreg(reg1)?, gpreg(reg2)?),
-            [0x6, reg, hi, lo] => Instr::LDL(gpreg(reg)?, byte(hi, lo)),
-            [0x7, reg, hi, lo] => Instr::ADDL(gpreg(reg)?, byte(hi, lo)),
+            [0x3, reg, hi, lo] => Instr::SEQ(gpreg(reg), byte(hi, lo)),
+            [0x4, reg, hi, lo] => Instr::SNELIT(gpreg(reg), byte(hi, lo)),
+            [0x5, reg1, reg2, 0x0] => Instr::SE(gpreg(reg1), gpreg(reg2)),
+            [0x5, reg1, reg2, 0x2] => Instr::PUSHRANGE(gpreg(reg1), gpreg(reg2)),
+            [0x5, reg1, reg2, 0x3] => Instr::POPRANGE(gpreg(reg1), gpreg(reg2)),
+            [0x6, reg, hi, lo] => Instr::LDL(gpreg(reg), byte(hi, lo)),
+            [0x7, reg, hi, lo] => Instr::ADDL(gpreg(reg), byte(hi, lo)),
             [0x8, reg1, reg2, op] => {
-                let reg1 = gpreg(reg1)?;
-                let reg2 = gpreg(reg2)?;
+                let reg1 = gpreg(reg1);
+                let reg2 = gpreg(reg2);
                 match op {
                     0x0 => Instr::LD(reg1, reg2),
                     0x1 => Instr::OR(reg1, reg2),
@@ -196,26 +267,190 @@ impl Instr {
                     0x6 => Instr::SHRC(reg1, reg2),
                     0x7 => Instr::SUBN(reg1, reg2),
                     0xE => Instr::SHLC(reg1, reg2),
-                    _ => return Err(Error::InstrErr(DecodeErr::Subcode(0x8, op))),
+                    _ => return Err(Error::InstrErr(DecodeErr::Undefined(value))),
                 }
             },
-            [0x9, reg1, reg2, 0x0] => Instr::SNE(gpreg(reg1)?, gpreg(reg2)?),
+            [0x9, reg1, reg2, 0x0] => Instr::SNE(gpreg(reg1), gpreg(reg2)),
             [0xA, hi, mid, lo] => Instr::LDI(addr(hi, mid, lo)),
-            [0xB, hi, mid, lo] => Instr::JP(addr(hi, mid, lo)),
-            [0xC, reg, hi, lo] => Instr::RND(gpreg(reg)?, byte(hi, lo)),
-            [0xD, reg1, reg2, nib] => Instr::DRW(gpreg(reg1)?, gpreg(reg2)?, u4::of(nib)),
-            [0xE, reg, 0x9, 0xE] => Instr::SKP(gpreg(reg)?),
-            [0xE, reg, 0xA, 0x1] => Instr::SKNP(gpreg(reg)?),
-            [0xF, reg, 0x0, 0x7] => Instr::MOVDT(gpreg(reg)?),
-            [0xF, reg, 0x0, 0xA] => Instr::LDKB(gpreg(reg)?),
-            [0xF, reg, 0x1, 0x5] => Instr::LDDT(gpreg(reg)?),
-            [0xF, reg, 0x1, 0x8] => Instr::LDST(gpreg(reg)?),
-            [0xF, reg, 0x1, 0xE] => Instr::ADDI(gpreg(reg)?),
-            [0xF, reg, 0x2, 0x9] => Instr::LDSPR(gpreg(reg)?),
-            [0xF, reg, 0x3, 0x3] => Instr::LDBCD(gpreg(reg)?),
-            [0xF, reg, 0x5, 0x5] => Instr::PUSHREG(gpreg(reg)?),
-            [0xF, reg, 0x6, 0x5] => Instr::POPREG(gpreg(reg)?),
-            _ => return Err(Error::InstrErr(DecodeErr::Opcode(value))),
+            [0xB, hi, mid, lo] => Instr::JPL(addr(hi, mid, lo)),
+            [0xC, reg, hi, lo] => Instr::RND(gpreg(reg), byte(hi, lo)),
+            [0xD, reg1, reg2, nib] => Instr::DRW(gpreg(reg1), gpreg(reg2), u4::of(nib)),
+            [0xE, reg, 0x9, 0xE] => Instr::SKP(gpreg(reg)),
+            [0xE, reg, 0xA, 0x1] => Instr::SKNP(gpreg(reg)),
+            [0xF, 0x0, 0x0, 0x0] => return Err(Error::InstrErr(DecodeErr::Long(value))),
+            [0xF, n, 0x0, 0x1] => Instr::PLANE(u4::of(n)),
+            [0xF, 0x0, 0x0, 0x2] => Instr::AUDIO,
+            [0xF, reg, 0x0, 0x7] => Instr::MOVDT(gpreg(reg)),
+            [0xF, reg, 0x0, 0xA] => Instr::LDKB(gpreg(reg)),
+            [0xF, reg, 0x1, 0x5] => Instr::LDDT(gpreg(reg)),
+            [0xF, reg, 0x1, 0x8] => Instr::LDST(gpreg(reg)),
+            [0xF, reg, 0x1, 0xE] => Instr::ADDI(gpreg(reg)),
+            [0xF, reg, 0x2, 0x9] => Instr::LDSPR(gpreg(reg)),
+            [0xF, reg, 0x3, 0x0] => Instr::LDHSPR(gpreg(reg)),
+            [0xF, reg, 0x3, 0x3] => Instr::LDBCD(gpreg(reg)),
+            [0xF, reg, 0x3, 0xA] => Instr::PITCH(gpreg(reg)),
+            [0xF, reg, 0x5, 0x5] => Instr::PUSHREG(gpreg(reg)),
+            [0xF, reg, 0x6, 0x5] => Instr::POPREG(gpreg(reg)),
+            [0xF, reg, 0x7, 0x5] => Instr::PUSHRPL(gpreg(reg)),
+            [0xF, reg, 0x8, 0x5] => Instr::POPRPL(gpreg(reg)),
+            _ => return Err(Error::InstrErr(DecodeErr::Undefined(value))),
         })
     }
+
+    /// Decode with the given dialect settings. Opcodes that `decode` rejects as
+    /// undefined are handled as `options.undefined` says. The 4-byte F000 nnnn is
+    /// not undefined and is still reported as `DecodeErr::Long`.
+    pub fn decode_with(value: u16, options: &DecodeOptions) -> Result<Self> {
+        match Instr::decode(value) {
+            Err(Error::InstrErr(err)) if !matches!(err, DecodeErr::Long(_)) => match options.undefined {
+                Undefined::Reject => Err(Error::InstrErr(err)),
+                Undefined::Nop => Ok(Instr::NOP(value)),
+                Undefined::Map(map) => map(value).ok_or(Error::InstrErr(err)),
+            },
+            decoded => decoded,
+        }
+    }
+
+    /// Like `decode_long`, with the given dialect settings
+    pub fn decode_long_with(value: u16, next: u16, options: &DecodeOptions) -> Result<Self> {
+        match value {
+            0xF000 => Ok(Instr::LDIL(next)),
+            _ => Instr::decode_with(value, options),
+        }
+    }
+
+    /// Decode an instruction that may span two words. `next` is only consumed
+    /// by the 4-byte F000 nnnn; every other opcode decodes exactly like `decode`.
+    pub fn decode_long(value: u16, next: u16) -> Result<Self> {
+        Instr::decode_long_with(value, next, &DecodeOptions::STRICT)
+    }
+
+    /// Size of the encoded instruction in bytes
+    pub fn byte_len(&self) -> u16 {
+        match self {
+            Instr::LDIL(_) => 4,
+            _ => 2,
+        }
+    }
+
+    /// Encode the instruction back to its opcode. For the 4-byte F000 nnnn this is
+    /// only the first word, use `to_bytes` to get the address too.  
+    /// `Instr::decode_long` gives back the instruction from `to_bytes`, with two exceptions:  
+    /// - `NOP` encodes to its undefined opcode, which only decodes back under `DecodeOptions::LENIENT`.  
+    /// - `SYS` addresses 0x0C0-0x0DF, 0x0E0, 0x0EE and 0x0FB-0x0FF are the opcodes of SCD, SCU, CLS,
+    ///   RET and SCR through HIGH, and decode as those. `decode` never produces these `SYS` values.
+    pub fn encode(&self) -> u16 {
+        let x = |reg: GPReg| (reg.to_idx() as u16) << 8;
+        let y = |reg: GPReg| (reg.to_idx() as u16) << 4;
+
+        use Instr::*;
+        match *self {
+            SYS(addr) => *addr,
+            CLS => 0x00E0,
+            RET => 0x00EE,
+            JP(addr) => 0x1000 | *addr,
+            CALL(addr) => 0x2000 | *addr,
+            SEQ(vx, byte) => 0x3000 | x(vx) | byte as u16,
+            SNELIT(vx, byte) => 0x4000 | x(vx) | byte as u16,
+            SE(vx, vy) => 0x5000 | x(vx) | y(vy),
+            LDL(vx, byte) => 0x6000 | x(vx) | byte as u16,
+            ADDL(vx, byte) => 0x7000 | x(vx) | byte as u16,
+            LD(vx, vy) => 0x8000 | x(vx) | y(vy),
+            OR(vx, vy) => 0x8001 | x(vx) | y(vy),
+            AND(vx, vy) => 0x8002 | x(vx) | y(vy),
+            XOR(vx, vy) => 0x8003 | x(vx) | y(vy),
+            ADDC(vx, vy) => 0x8004 | x(vx) | y(vy),
+            SUBC(vx, vy) => 0x8005 | x(vx) | y(vy),
+            SHRC(vx, vy) => 0x8006 | x(vx) | y(vy),
+            SUBN(vx, vy) => 0x8007 | x(vx) | y(vy),
+            SHLC(vx, vy) => 0x800E | x(vx) | y(vy),
+            SNE(vx, vy) => 0x9000 | x(vx) | y(vy),
+            LDI(addr) => 0xA000 | *addr,
+            JPL(addr) => 0xB000 | *addr,
+            RND(vx, byte) => 0xC000 | x(vx) | byte as u16,
+            DRW(vx, vy, n) => 0xD000 | x(vx) | y(vy) | *n as u16,
+            SKP(vx) => 0xE09E | x(vx),
+            SKNP(vx) => 0xE0A1 | x(vx),
+            MOVDT(vx) => 0xF007 | x(vx),
+            LDKB(vx) => 0xF00A | x(vx),
+            LDDT(vx) => 0xF015 | x(vx),
+            LDST(vx) => 0xF018 | x(vx),
+            ADDI(vx) => 0xF01E | x(vx),
+            LDSPR(vx) => 0xF029 | x(vx),
+            LDBCD(vx) => 0xF033 | x(vx),
+            PUSHREG(vx) => 0xF055 | x(vx),
+            POPREG(vx) => 0xF065 | x(vx),
+            SCD(n) => 0x00C0 | *n as u16,
+            SCR => 0x00FB,
+            SCL => 0x00FC,
+            EXIT => 0x00FD,
+            LOW => 0x00FE,
+            HIGH => 0x00FF,
+            LDHSPR(vx) => 0xF030 | x(vx),
+            PUSHRPL(vx) => 0xF075 | x(vx),
+            POPRPL(vx) => 0xF085 | x(vx),
+            SCU(n) => 0x00D0 | *n as u16,
+            PUSHRANGE(vx, vy) => 0x5002 | x(vx) | y(vy),
+            POPRANGE(vx, vy) => 0x5003 | x(vx) | y(vy),
+            LDIL(_) => 0xF000,
+            PLANE(n) => 0xF001 | (*n as u16) << 8,
+            AUDIO => 0xF002,
+            PITCH(vx) => 0xF03A | x(vx),
+            NOP(opcode) => opcode,
+        }
+    }
+
+    /// The big endian bytes of the full instruction, as they appear in a ROM
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.encode().to_be_bytes().to_vec();
+        if let Instr::LDIL(addr) = self {
+            bytes.extend_from_slice(&addr.to_be_bytes());
+        }
+        bytes
+    }
+
+    /// Encode a sequence of instructions into ROM bytes
+    pub fn encode_all(instrs: &[Instr]) -> Vec<u8> {
+        instrs.iter()
+            .flat_map(Instr::to_bytes)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use shared::numtypes::u12;
+
+    use super::Instr;
+    use crate::options::DecodeOptions;
+
+    #[test]
+    fn roundtrip() {
+        //Every variant, with every operand of the 2-byte ones and a few long addresses
+        let long = [0x0000, 0x1234, 0xBEEF, 0xFFFF].map(Instr::LDIL);
+        let instrs = (0..=u16::MAX)
+            .filter_map(|opcode| Instr::decode_with(opcode, &DecodeOptions::LENIENT).ok())
+            .chain(long);
+        for instr in instrs {
+            let bytes = instr.to_bytes();
+            assert_eq!(bytes.len(), instr.byte_len() as usize);
+            let word = |idx: usize| bytes.get(idx..idx + 2).map_or(0, |word| u16::from_be_bytes([word[0], word[1]]));
+
+            let decoded = match instr {
+                Instr::NOP(_) => Instr::decode_long_with(word(0), word(2), &DecodeOptions::LENIENT),
+                _ => Instr::decode_long(word(0), word(2)),
+            };
+            assert_eq!(decoded.ok(), Some(instr), "{instr:?} did not decode back from {bytes:02X?}");
+        }
+
+        //The SYS addresses documented on `encode` are the only ones that don't come back
+        let collisions: Vec<u16> = (0..=0xFFF)
+            .filter(|&addr| {
+                let sys = Instr::SYS(u12::of(addr));
+                Instr::decode(sys.encode()).ok() != Some(sys)
+            })
+            .collect();
+        let documented: Vec<u16> = (0x0C0..=0x0DF).chain([0x0E0, 0x0EE]).chain(0x0FB..=0x0FF).collect();
+        assert_eq!(collisions, documented);
+    }
 }
\ No newline at end of file
@@ -1,4 +1,13 @@
-mod errors;
+pub mod errors;
 pub(crate) use errors::Result;
 
-pub mod instructions;
\ No newline at end of file
+pub mod instructions;
+pub mod options;
+pub mod conformance;
+pub mod flow;
+pub mod meta;
+pub mod syntax;
+pub mod disasm;
+pub mod cfg;
+pub mod asm;
+pub mod octo;
\ No newline at end of file
@@ -0,0 +1,175 @@
+//Static facts about what an instruction touches.
+//
+//These are conservative: a register or effect is listed if any of the quirks can make
+//the instruction use it. E.g. `OR` writes VF because of the VF reset quirk, and `JP V0`
+//reads Vx too because of the jumping quirk.
+
+use shared::reg::GPReg;
+
+use crate::instructions::Instr;
+
+/// State an instruction may touch besides the general purpose registers
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Default)]
+pub struct Effects {
+    pub reads_i: bool,
+    pub writes_i: bool,
+    pub reads_memory: bool,
+    pub writes_memory: bool,
+    /// Reads or writes the delay or sound timer
+    pub timers: bool,
+    pub display: bool,
+    pub keyboard: bool,
+    /// Pushes or pops the call stack
+    pub stack: bool,
+    /// Changes the sound: the sound timer, the XO-CHIP pattern or its pitch
+    pub audio: bool,
+    pub random: bool,
+}
+
+impl Effects {
+    /// Whether the instruction only reads and writes registers
+    pub fn is_pure(&self) -> bool {
+        *self == Effects::default()
+    }
+}
+
+//Vx through Vy inclusive, in either direction like the XO-CHIP save/load ranges
+fn reg_range(x: GPReg, y: GPReg) -> Vec<GPReg> {
+    let (lo, hi) = (x.to_idx().min(y.to_idx()), x.to_idx().max(y.to_idx()));
+    (lo..=hi).filter_map(|idx| GPReg::indexed(idx as u8)).collect()
+}
+
+fn unique(regs: &[GPReg]) -> Vec<GPReg> {
+    let mut out: Vec<GPReg> = Vec::with_capacity(regs.len());
+    for reg in regs {
+        if !out.contains(reg) {
+            out.push(*reg);
+        }
+    }
+    out
+}
+
+impl Instr {
+    /// The address operand of SYS, JP, CALL, LD I and JP V0
+    pub fn address(&self) -> Option<u16> {
+        use Instr::*;
+        match *self {
+            SYS(nnn) | JP(nnn) | CALL(nnn) | LDI(nnn) | JPL(nnn) => Some(*nnn),
+            LDIL(addr) => Some(addr),
+            _ => None,
+        }
+    }
+
+    /// General purpose registers the instruction may read
+    pub fn reads(&self) -> Vec<GPReg> {
+        use GPReg::*;
+        use Instr::*;
+        match *self {
+            SEQ(x, _) | SNELIT(x, _) | ADDL(x, _) | SKP(x) | SKNP(x) | LDDT(x) | LDST(x) | ADDI(x)
+            | LDSPR(x) | LDHSPR(x) | LDBCD(x) | PITCH(x) => vec![x],
+            SE(x, y) | SNE(x, y) | OR(x, y) | AND(x, y) | XOR(x, y) | ADDC(x, y) | SUBC(x, y)
+            | SHRC(x, y) | SUBN(x, y) | SHLC(x, y) | DRW(x, y, _) => unique(&[x, y]),
+            LD(_, y) => vec![y],
+            JPL(addr) => {
+                let vx = GPReg::indexed((*addr >> 8) as u8).expect("a nibble is always a register");
+                unique(&[V0, vx])
+            },
+            PUSHREG(x) | PUSHRPL(x) => reg_range(V0, x),
+            PUSHRANGE(x, y) => reg_range(x, y),
+            _ => vec![],
+        }
+    }
+
+    /// General purpose registers the instruction may write
+    pub fn writes(&self) -> Vec<GPReg> {
+        use GPReg::*;
+        use Instr::*;
+        match *self {
+            LDL(x, _) | ADDL(x, _) | LD(x, _) | RND(x, _) | MOVDT(x) | LDKB(x) => vec![x],
+            OR(x, _) | AND(x, _) | XOR(x, _) | ADDC(x, _) | SUBC(x, _) | SHRC(x, _) | SUBN(x, _)
+            | SHLC(x, _) => unique(&[x, VF]),
+            DRW(..) | ADDI(_) => vec![VF],
+            POPREG(x) | POPRPL(x) => reg_range(V0, x),
+            POPRANGE(x, y) => reg_range(x, y),
+            _ => vec![],
+        }
+    }
+
+    pub fn effects(&self) -> Effects {
+        let mut fx = Effects::default();
+        use Instr::*;
+        match self {
+            CLS | SCD(_) | SCU(_) | SCR | SCL | LOW | HIGH | PLANE(_) => fx.display = true,
+            CALL(_) | RET => fx.stack = true,
+            LDI(_) | LDIL(_) | LDSPR(_) | LDHSPR(_) => fx.writes_i = true,
+            ADDI(_) => {
+                fx.reads_i = true;
+                fx.writes_i = true;
+            },
+            DRW(..) => {
+                fx.reads_i = true;
+                fx.reads_memory = true;
+                fx.display = true;
+            },
+            LDBCD(_) => {
+                fx.reads_i = true;
+                fx.writes_memory = true;
+            },
+            //The memory quirk moves I past the registers
+            PUSHREG(_) => {
+                fx.reads_i = true;
+                fx.writes_i = true;
+                fx.writes_memory = true;
+            },
+            POPREG(_) => {
+                fx.reads_i = true;
+                fx.writes_i = true;
+                fx.reads_memory = true;
+            },
+            PUSHRANGE(..) => {
+                fx.reads_i = true;
+                fx.writes_memory = true;
+            },
+            POPRANGE(..) => {
+                fx.reads_i = true;
+                fx.reads_memory = true;
+            },
+            MOVDT(_) | LDDT(_) => fx.timers = true,
+            LDST(_) => {
+                fx.timers = true;
+                fx.audio = true;
+            },
+            AUDIO => {
+                fx.reads_i = true;
+                fx.reads_memory = true;
+                fx.audio = true;
+            },
+            PITCH(_) => fx.audio = true,
+            SKP(_) | SKNP(_) | LDKB(_) => fx.keyboard = true,
+            RND(..) => fx.random = true,
+            _ => {},
+        }
+        fx
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use GPReg::*;
+
+    #[test]
+    fn registers_and_effects() {
+        let decode = |opcode| Instr::decode(opcode).unwrap();
+        assert_eq!(decode(0x8124).reads(), [V1, V2]);
+        assert_eq!(decode(0x8124).writes(), [V1, VF]);
+        assert_eq!(decode(0xF265).writes(), [V0, V1, V2]);
+        assert_eq!(decode(0x5532).reads(), [V3, V4, V5]);
+        assert_eq!(decode(0xB300).reads(), [V0, V3]);
+
+        let drw = decode(0xD125).effects();
+        assert!(drw.display && drw.reads_memory && !drw.writes_memory);
+        assert!(decode(0x8120).effects().is_pure());
+        assert!(decode(0xE19E).effects().keyboard);
+    }
+}
\ No newline at end of file
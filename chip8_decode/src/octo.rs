@@ -0,0 +1,926 @@
+//Compiler for Octo (.8o) source, https://johnearnest.github.io/Octo/docs/Manual.html
+//
+//Supported:
+//  : name                  label, called by writing its name or jumped to with jump
+//  :const name value       named constant
+//  :alias name vx          another name for a register
+//  :macro name args { }    textual macro, invoked as `name arg ...`
+//  :calc name { expr }     compile time expression, evaluated right to left without precedence
+//  :byte value             emit a byte, value may be { expr }. Bare numbers emit bytes too.
+//  :call addr              call an address, e.g. a label defined later
+//  loop ... while c ... again
+//  if c then stmt, if c begin ... else ... end
+//  conditions ==, !=, key, -key, and <, >, <=, >= which work the answer out in vF
+//  first like Octo does, so they overwrite it
+//  the register and I operators, and the SUPER-CHIP and XO-CHIP statements
+//
+//Like Octo, the ROM starts with a jump to `: main`, so main can come after the
+//subroutines it calls. A program without main is an error.
+//
+//Tokens are separated by whitespace and # starts a comment. Like the assembler, each
+//statement is built as an `Instr` and encoded with `Instr::to_bytes`.
+
+use std::collections::{BTreeMap, HashMap};
+
+use shared::numtypes::{u12, u4};
+use shared::reg::GPReg;
+
+use crate::asm::{AsmError, AsmErrorKind};
+use crate::disasm::LOAD_ADDR;
+use crate::instructions::Instr;
+
+//Guards against macros that expand into themselves
+const MAX_EXPANSIONS: usize = 10_000;
+
+/// A named value in a compiled program
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Symbol {
+    Label(u16),
+    Const(f64),
+    Alias(GPReg),
+}
+
+/// A compiled ROM and its symbol table
+#[derive(Clone, Debug, PartialEq)]
+pub struct Program {
+    /// Address of the first byte
+    pub base: u16,
+    pub bytes: Vec<u8>,
+    pub symbols: BTreeMap<String, Symbol>,
+}
+
+type Result<T> = std::result::Result<T, AsmError>;
+
+/// Compile Octo source into a ROM loaded at `LOAD_ADDR`
+pub fn compile(src: &str) -> Result<Program> {
+    let mut compiler = Compiler {
+        tokens: tokenize(src),
+        pos: 0,
+        bytes: Vec::new(),
+        symbols: BTreeMap::new(),
+        macros: HashMap::new(),
+        fixups: Vec::new(),
+        blocks: Vec::new(),
+        expansions: 0,
+    };
+
+    //Execution starts at LOAD_ADDR, so reserve it for the jump to main
+    let main = Token { text: "main".into(), line: 1, col: 1 };
+    compiler.fixups.push(Fixup { offset: 0, kind: FixupKind::Jump, tok: main });
+    compiler.placeholder_jump();
+
+    while compiler.pos < compiler.tokens.len() {
+        compiler.statement()?;
+    }
+    if let Some((block, tok)) = compiler.blocks.pop() {
+        let expected = match block {
+            Block::Loop { .. } => "again",
+            Block::If { .. } | Block::Else { .. } => "end",
+        };
+        return Err(tok.error(AsmErrorKind::Expected { expected, found: "end of file".into() }));
+    }
+    compiler.resolve_fixups()?;
+
+    Ok(Program { base: LOAD_ADDR, bytes: compiler.bytes, symbols: compiler.symbols })
+}
+
+#[derive(Clone, Debug)]
+struct Token {
+    text: String,
+    line: usize,
+    col: usize,
+}
+
+impl Token {
+    fn error(&self, kind: AsmErrorKind) -> AsmError {
+        AsmError { line: self.line, col: self.col, kind }
+    }
+
+    fn unexpected(&self, expected: &'static str) -> AsmError {
+        self.error(AsmErrorKind::Expected { expected, found: format!("\"{}\"", self.text) })
+    }
+}
+
+fn tokenize(src: &str) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    for (idx, line) in src.lines().enumerate() {
+        let line_text = line.split('#').next().unwrap_or_default();
+        let mut chars = line_text.char_indices().peekable();
+
+        while let Some(&(start, ch)) = chars.peek() {
+            if ch.is_whitespace() {
+                chars.next();
+                continue;
+            }
+
+            let mut end = start;
+            while let Some(&(pos, ch)) = chars.peek() {
+                if ch.is_whitespace() {
+                    break;
+                }
+                end = pos + ch.len_utf8();
+                chars.next();
+            }
+            tokens.push(Token {
+                text: line_text[start..end].to_owned(),
+                line: idx + 1,
+                col: line_text[..start].chars().count() + 1,
+            });
+        }
+    }
+    tokens
+}
+
+fn parse_number(text: &str) -> Option<i64> {
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) => (true, digits),
+        None => (false, text),
+    };
+    let lower = digits.to_lowercase();
+    let value = if let Some(hex) = lower.strip_prefix("0x") {
+        i64::from_str_radix(hex, 16).ok()?
+    } else if let Some(bin) = lower.strip_prefix("0b") {
+        i64::from_str_radix(bin, 2).ok()?
+    } else if lower.starts_with(|ch: char| ch.is_ascii_digit()) {
+        lower.parse().ok()?
+    } else {
+        return None;
+    };
+    Some(if negative { -value } else { value })
+}
+
+fn in_range(tok: &Token, value: i64, min: i64, max: i64) -> Result<i64> {
+    if !(min..=max).contains(&value) {
+        return Err(tok.error(AsmErrorKind::OutOfRange { value, min, max }));
+    }
+    Ok(value)
+}
+
+#[derive(Clone, Debug)]
+struct Macro {
+    args: Vec<String>,
+    body: Vec<Token>,
+}
+
+//Address operands that referenced a label before it was defined
+#[derive(Copy, Clone, Debug)]
+enum FixupKind {
+    Jump,
+    Jump0,
+    Call,
+    LoadI,
+    LoadILong,
+}
+
+#[derive(Clone, Debug)]
+struct Fixup {
+    offset: usize,
+    kind: FixupKind,
+    tok: Token,
+}
+
+//Open control flow blocks. Jumps are the byte offsets of placeholder JPs to patch.
+#[derive(Clone, Debug)]
+enum Block {
+    Loop { start: u16, breaks: Vec<usize> },
+    If { jump: usize },
+    Else { jump: usize },
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Rhs {
+    Reg(GPReg),
+    Byte(u8),
+}
+
+#[derive(Copy, Clone, Debug)]
+enum Cond {
+    Eq(GPReg, Rhs),
+    Ne(GPReg, Rhs),
+    Key(GPReg),
+    NotKey(GPReg),
+    Less(GPReg, Rhs),
+    Greater(GPReg, Rhs),
+    LessEq(GPReg, Rhs),
+    GreaterEq(GPReg, Rhs),
+}
+
+impl Cond {
+    //The instructions that skip the next one when the condition evaluates to `when`
+    fn skip_when(self, when: bool) -> Vec<Instr> {
+        use Instr::*;
+        let cond = match (self, when) {
+            (cond, true) => cond,
+            (Cond::Eq(x, rhs), false) => Cond::Ne(x, rhs),
+            (Cond::Ne(x, rhs), false) => Cond::Eq(x, rhs),
+            (Cond::Key(x), false) => Cond::NotKey(x),
+            (Cond::NotKey(x), false) => Cond::Key(x),
+            (Cond::Less(x, rhs), false) => Cond::GreaterEq(x, rhs),
+            (Cond::Greater(x, rhs), false) => Cond::LessEq(x, rhs),
+            (Cond::LessEq(x, rhs), false) => Cond::Greater(x, rhs),
+            (Cond::GreaterEq(x, rhs), false) => Cond::Less(x, rhs),
+        };
+
+        //vF := rhs, then vF =- x sets vF to x >= rhs, and vF -= x sets it to x <= rhs
+        let compare = |rhs, sub: Instr, flag| {
+            let load = match rhs {
+                Rhs::Reg(y) => LD(GPReg::VF, y),
+                Rhs::Byte(kk) => LDL(GPReg::VF, kk),
+            };
+            vec![load, sub, SEQ(GPReg::VF, flag)]
+        };
+        match cond {
+            Cond::Eq(x, Rhs::Byte(kk)) => vec![SEQ(x, kk)],
+            Cond::Eq(x, Rhs::Reg(y)) => vec![SE(x, y)],
+            Cond::Ne(x, Rhs::Byte(kk)) => vec![SNELIT(x, kk)],
+            Cond::Ne(x, Rhs::Reg(y)) => vec![SNE(x, y)],
+            Cond::Key(x) => vec![SKP(x)],
+            Cond::NotKey(x) => vec![SKNP(x)],
+            Cond::Less(x, rhs) => compare(rhs, SUBN(GPReg::VF, x), 0),
+            Cond::GreaterEq(x, rhs) => compare(rhs, SUBN(GPReg::VF, x), 1),
+            Cond::Greater(x, rhs) => compare(rhs, SUBC(GPReg::VF, x), 0),
+            Cond::LessEq(x, rhs) => compare(rhs, SUBC(GPReg::VF, x), 1),
+        }
+    }
+}
+
+struct Compiler {
+    tokens: Vec<Token>,
+    pos: usize,
+    bytes: Vec<u8>,
+    symbols: BTreeMap<String, Symbol>,
+    macros: HashMap<String, Macro>,
+    fixups: Vec<Fixup>,
+    blocks: Vec<(Block, Token)>,
+    expansions: usize,
+}
+
+impl Compiler {
+    fn here(&self) -> u16 {
+        LOAD_ADDR + self.bytes.len() as u16
+    }
+
+    fn emit(&mut self, instr: Instr) {
+        self.bytes.extend(instr.to_bytes());
+    }
+
+    fn next(&mut self) -> Result<Token> {
+        let tok = match self.tokens.get(self.pos) {
+            Some(tok) => tok.clone(),
+            None => {
+                let last = self.tokens.last().map_or((1, 1), |tok| (tok.line, tok.col + tok.text.chars().count()));
+                let kind = AsmErrorKind::Expected { expected: "another token", found: "end of file".into() };
+                return Err(AsmError { line: last.0, col: last.1, kind });
+            },
+        };
+        self.pos += 1;
+        Ok(tok)
+    }
+
+    fn peek_is(&self, text: &str) -> bool {
+        self.tokens.get(self.pos).is_some_and(|tok| tok.text == text)
+    }
+
+    fn expect(&mut self, text: &'static str) -> Result<()> {
+        let tok = self.next()?;
+        if tok.text != text {
+            return Err(tok.unexpected(text));
+        }
+        Ok(())
+    }
+
+    fn register_of(&self, text: &str) -> Option<GPReg> {
+        if let Some(Symbol::Alias(reg)) = self.symbols.get(text) {
+            return Some(*reg);
+        }
+        match text.as_bytes() {
+            [b'v' | b'V', idx] => GPReg::indexed((*idx as char).to_digit(16)? as u8),
+            _ => None,
+        }
+    }
+
+    fn register(&mut self) -> Result<GPReg> {
+        let tok = self.next()?;
+        self.register_of(&tok.text).ok_or_else(|| tok.unexpected("a register"))
+    }
+
+    //A number, constant or already defined label
+    fn value_of(&self, tok: &Token) -> Result<i64> {
+        if let Some(num) = parse_number(&tok.text) {
+            return Ok(num);
+        }
+        match self.symbols.get(&tok.text) {
+            Some(Symbol::Const(value)) => Ok(*value as i64),
+            Some(Symbol::Label(addr)) => Ok(*addr as i64),
+            Some(Symbol::Alias(_)) => Err(tok.unexpected("a value")),
+            None => Err(tok.error(AsmErrorKind::UndefinedLabel(tok.text.clone()))),
+        }
+    }
+
+    fn value_in(&mut self, min: i64, max: i64) -> Result<i64> {
+        let tok = self.next()?;
+        let value = self.value_of(&tok)?;
+        in_range(&tok, value, min, max)
+    }
+
+    fn byte(&mut self) -> Result<u8> {
+        Ok(self.value_in(-0x80, 0xFF)? as u8)
+    }
+
+    fn nibble(&mut self) -> Result<u4> {
+        Ok(u4::of(self.value_in(0, 0xF)? as u8))
+    }
+
+    //An address operand. Labels that aren't defined yet are patched at the end.
+    fn address(&mut self, kind: FixupKind) -> Result<()> {
+        let tok = self.next()?;
+        let max = if matches!(kind, FixupKind::LoadILong) { 0xFFFF } else { 0xFFF };
+
+        let addr = if parse_number(&tok.text).is_none() && !self.symbols.contains_key(&tok.text) {
+            self.fixups.push(Fixup { offset: self.bytes.len(), kind, tok });
+            0
+        } else {
+            let value = self.value_of(&tok)?;
+            if !(0..=max).contains(&value) {
+                return Err(tok.error(AsmErrorKind::OutOfRange { value, min: 0, max }));
+            }
+            value as u16
+        };
+        self.emit(address_instr(kind, addr));
+        Ok(())
+    }
+
+    fn define(&mut self, tok: &Token, symbol: Symbol) -> Result<()> {
+        //Aliases are routinely renamed as register use changes, everything else is defined once
+        let redefinable = matches!((self.symbols.get(&tok.text), symbol), (Some(Symbol::Alias(_)), Symbol::Alias(_)));
+        if self.symbols.contains_key(&tok.text) && !redefinable || self.macros.contains_key(&tok.text) {
+            return Err(tok.error(AsmErrorKind::DuplicateLabel(tok.text.clone())));
+        }
+        if parse_number(&tok.text).is_some() || self.register_of(&tok.text).is_some() && !redefinable {
+            return Err(tok.unexpected("a name"));
+        }
+        self.symbols.insert(tok.text.clone(), symbol);
+        Ok(())
+    }
+
+    //Tokens up to the matching }, after the { has been consumed
+    fn braced(&mut self) -> Result<Vec<Token>> {
+        let mut depth = 0;
+        let mut body = Vec::new();
+        loop {
+            let tok = self.next()?;
+            match tok.text.as_str() {
+                "{" => depth += 1,
+                "}" if depth == 0 => return Ok(body),
+                "}" => depth -= 1,
+                _ => {},
+            }
+            body.push(tok);
+        }
+    }
+
+    fn cond(&mut self) -> Result<Cond> {
+        let reg = self.register()?;
+        let op = self.next()?;
+        let rhs = |c: &mut Compiler| -> Result<Rhs> {
+            let tok = c.next()?;
+            match c.register_of(&tok.text) {
+                Some(reg) => Ok(Rhs::Reg(reg)),
+                None => {
+                    c.pos -= 1;
+                    Ok(Rhs::Byte(c.byte()?))
+                },
+            }
+        };
+        match op.text.as_str() {
+            "==" => Ok(Cond::Eq(reg, rhs(self)?)),
+            "!=" => Ok(Cond::Ne(reg, rhs(self)?)),
+            "<" => Ok(Cond::Less(reg, rhs(self)?)),
+            ">" => Ok(Cond::Greater(reg, rhs(self)?)),
+            "<=" => Ok(Cond::LessEq(reg, rhs(self)?)),
+            ">=" => Ok(Cond::GreaterEq(reg, rhs(self)?)),
+            "key" => Ok(Cond::Key(reg)),
+            "-key" => Ok(Cond::NotKey(reg)),
+            _ => Err(op.unexpected("==, !=, <, >, <=, >=, key or -key")),
+        }
+    }
+
+    //JP only reaches 12 bit addresses, which a 64K XO-CHIP program can outgrow
+    fn jump_to(target: u16, tok: &Token) -> Result<Instr> {
+        if target > 0xFFF {
+            return Err(tok.error(AsmErrorKind::OutOfRange { value: target as i64, min: 0, max: 0xFFF }));
+        }
+        Ok(Instr::JP(u12::of(target)))
+    }
+
+    fn patch_jump(&mut self, offset: usize, tok: &Token) -> Result<()> {
+        let bytes = Compiler::jump_to(self.here(), tok)?.to_bytes();
+        self.bytes[offset..offset + bytes.len()].copy_from_slice(&bytes);
+        Ok(())
+    }
+
+    fn emit_skip(&mut self, cond: Cond, when: bool) {
+        for instr in cond.skip_when(when) {
+            self.emit(instr);
+        }
+    }
+
+    fn placeholder_jump(&mut self) -> usize {
+        let offset = self.bytes.len();
+        self.emit(Instr::JP(u12::of(0)));
+        offset
+    }
+
+    fn statement(&mut self) -> Result<()> {
+        let tok = self.next()?;
+        use Instr::*;
+
+        match tok.text.as_str() {
+            ":" => {
+                let name = self.next()?;
+                let here = self.here();
+                self.define(&name, Symbol::Label(here))?;
+            },
+            ":const" => {
+                let name = self.next()?;
+                let value_tok = self.next()?;
+                let value = self.value_of(&value_tok)?;
+                self.define(&name, Symbol::Const(value as f64))?;
+            },
+            ":alias" => {
+                let name = self.next()?;
+                let reg = self.register()?;
+                self.define(&name, Symbol::Alias(reg))?;
+            },
+            ":macro" => {
+                let name = self.next()?;
+                let mut args = Vec::new();
+                loop {
+                    let arg = self.next()?;
+                    if arg.text == "{" {
+                        break;
+                    }
+                    args.push(arg.text);
+                }
+                let body = self.braced()?;
+                if self.symbols.contains_key(&name.text) || self.macros.insert(name.text.clone(), Macro { args, body }).is_some() {
+                    return Err(name.error(AsmErrorKind::DuplicateLabel(name.text.clone())));
+                }
+            },
+            ":calc" => {
+                let name = self.next()?;
+                self.expect("{")?;
+                let body = self.braced()?;
+                let value = self.calc(&name, &body)?;
+                self.define(&name, Symbol::Const(value))?;
+            },
+            ":byte" => {
+                let value = if self.peek_is("{") {
+                    self.pos += 1;
+                    let body = self.braced()?;
+                    let value = self.calc(&tok, &body)?;
+                    in_range(&tok, value as i64, -0x80, 0xFF)?
+                } else {
+                    self.value_in(-0x80, 0xFF)?
+                };
+                self.bytes.push(value as u8);
+            },
+            ":call" => self.address(FixupKind::Call)?,
+
+            "clear" => self.emit(CLS),
+            "return" | ";" => self.emit(RET),
+            "hires" => self.emit(HIGH),
+            "lores" => self.emit(LOW),
+            "exit" => self.emit(EXIT),
+            "scroll-left" => self.emit(SCL),
+            "scroll-right" => self.emit(SCR),
+            "audio" => self.emit(AUDIO),
+            "scroll-down" => {
+                let n = self.nibble()?;
+                self.emit(SCD(n));
+            },
+            "scroll-up" => {
+                let n = self.nibble()?;
+                self.emit(SCU(n));
+            },
+            "plane" => {
+                let n = self.nibble()?;
+                self.emit(PLANE(n));
+            },
+            "jump" => self.address(FixupKind::Jump)?,
+            "jump0" => self.address(FixupKind::Jump0)?,
+            "bcd" => {
+                let x = self.register()?;
+                self.emit(LDBCD(x));
+            },
+            "save" | "load" => {
+                let x = self.register()?;
+                let instr = if self.peek_is("-") {
+                    self.pos += 1;
+                    let y = self.register()?;
+                    if tok.text == "save" { PUSHRANGE(x, y) } else { POPRANGE(x, y) }
+                } else if tok.text == "save" {
+                    PUSHREG(x)
+                } else {
+                    POPREG(x)
+                };
+                self.emit(instr);
+            },
+            "saveflags" => {
+                let x = self.register()?;
+                self.emit(PUSHRPL(x));
+            },
+            "loadflags" => {
+                let x = self.register()?;
+                self.emit(POPRPL(x));
+            },
+            "sprite" => {
+                let x = self.register()?;
+                let y = self.register()?;
+                let n = self.nibble()?;
+                self.emit(DRW(x, y, n));
+            },
+            "delay" | "buzzer" | "pitch" => {
+                self.expect(":=")?;
+                let x = self.register()?;
+                self.emit(match tok.text.as_str() {
+                    "delay" => LDDT(x),
+                    "buzzer" => LDST(x),
+                    _ => PITCH(x),
+                });
+            },
+            "i" => self.i_statement()?,
+
+            "if" => {
+                let cond = self.cond()?;
+                let kw = self.next()?;
+                match kw.text.as_str() {
+                    "then" => self.emit_skip(cond, false),
+                    "begin" => {
+                        self.emit_skip(cond, true);
+                        let jump = self.placeholder_jump();
+                        self.blocks.push((Block::If { jump }, tok));
+                    },
+                    _ => return Err(kw.unexpected("then or begin")),
+                }
+            },
+            "else" => {
+                let Some((Block::If { jump }, open)) = self.blocks.pop() else {
+                    return Err(tok.unexpected("else only after if ... begin"));
+                };
+                let skip_else = self.placeholder_jump();
+                self.patch_jump(jump, &tok)?;
+                self.blocks.push((Block::Else { jump: skip_else }, open));
+            },
+            "end" => {
+                let Some((Block::If { jump } | Block::Else { jump }, _)) = self.blocks.pop() else {
+                    return Err(tok.unexpected("end only after if ... begin"));
+                };
+                self.patch_jump(jump, &tok)?;
+            },
+            "loop" => {
+                let start = self.here();
+                self.blocks.push((Block::Loop { start, breaks: Vec::new() }, tok));
+            },
+            "while" => {
+                let cond = self.cond()?;
+                self.emit_skip(cond, true);
+                let jump = self.placeholder_jump();
+                match self.blocks.iter_mut().rev().find_map(|(block, _)| match block {
+                    Block::Loop { breaks, .. } => Some(breaks),
+                    _ => None,
+                }) {
+                    Some(breaks) => breaks.push(jump),
+                    None => return Err(tok.unexpected("while only inside loop")),
+                }
+            },
+            "again" => {
+                let Some((Block::Loop { start, breaks }, _)) = self.blocks.pop() else {
+                    return Err(tok.unexpected("again only after loop"));
+                };
+                let jump = Compiler::jump_to(start, &tok)?;
+                self.emit(jump);
+                for jump in breaks {
+                    self.patch_jump(jump, &tok)?;
+                }
+            },
+
+            text if parse_number(text).is_some() => {
+                self.pos -= 1;
+                let byte = self.byte()?;
+                self.bytes.push(byte);
+            },
+            text if self.register_of(text).is_some() => {
+                self.pos -= 1;
+                self.register_statement()?;
+            },
+            text if self.macros.contains_key(text) => self.expand(&tok)?,
+            text if text.starts_with(':') || matches!(self.symbols.get(text), Some(Symbol::Const(_))) => {
+                return Err(tok.error(AsmErrorKind::UnknownMnemonic(tok.text.clone())));
+            },
+            //Anything else is a subroutine call, possibly to a label defined later
+            _ => {
+                self.pos -= 1;
+                self.address(FixupKind::Call)?;
+            },
+        }
+        Ok(())
+    }
+
+    fn i_statement(&mut self) -> Result<()> {
+        let op = self.next()?;
+        match op.text.as_str() {
+            ":=" => {
+                if self.peek_is("long") {
+                    self.pos += 1;
+                    return self.address(FixupKind::LoadILong);
+                }
+                if self.peek_is("hex") || self.peek_is("bighex") {
+                    let big = self.next()?.text == "bighex";
+                    let x = self.register()?;
+                    self.emit(if big { Instr::LDHSPR(x) } else { Instr::LDSPR(x) });
+                    return Ok(());
+                }
+                self.address(FixupKind::LoadI)
+            },
+            "+=" => {
+                let x = self.register()?;
+                self.emit(Instr::ADDI(x));
+                Ok(())
+            },
+            _ => Err(op.unexpected(":= or +=")),
+        }
+    }
+
+    fn register_statement(&mut self) -> Result<()> {
+        use Instr::*;
+        let x = self.register()?;
+        let op = self.next()?;
+
+        let operand = self.next()?;
+        let y = self.register_of(&operand.text);
+        let instr = match (op.text.as_str(), y) {
+            (":=", Some(y)) => LD(x, y),
+            (":=", None) => match operand.text.as_str() {
+                "random" => RND(x, self.byte()?),
+                "key" => LDKB(x),
+                "delay" => MOVDT(x),
+                _ => {
+                    self.pos -= 1;
+                    LDL(x, self.byte()?)
+                },
+            },
+            ("+=", Some(y)) => ADDC(x, y),
+            ("+=", None) => {
+                self.pos -= 1;
+                ADDL(x, self.byte()?)
+            },
+            ("-=", Some(y)) => SUBC(x, y),
+            ("-=", None) => {
+                self.pos -= 1;
+                ADDL(x, self.byte()?.wrapping_neg())
+            },
+            ("=-", Some(y)) => SUBN(x, y),
+            ("|=", Some(y)) => OR(x, y),
+            ("&=", Some(y)) => AND(x, y),
+            ("^=", Some(y)) => XOR(x, y),
+            (">>=", Some(y)) => SHRC(x, y),
+            ("<<=", Some(y)) => SHLC(x, y),
+            ("=-" | "|=" | "&=" | "^=" | ">>=" | "<<=", None) => return Err(operand.unexpected("a register")),
+            _ => return Err(op.unexpected("a register operator")),
+        };
+        self.emit(instr);
+        Ok(())
+    }
+
+    fn expand(&mut self, name: &Token) -> Result<()> {
+        self.expansions += 1;
+        if self.expansions > MAX_EXPANSIONS {
+            return Err(name.unexpected("a macro that doesn't expand forever"));
+        }
+
+        let mac = self.macros[&name.text].clone();
+        let mut args = HashMap::new();
+        for arg in &mac.args {
+            args.insert(arg.clone(), self.next()?.text);
+        }
+
+        let body = mac.body.into_iter().map(|mut tok| {
+            if let Some(value) = args.get(&tok.text) {
+                tok.text = value.clone();
+            }
+            tok
+        });
+        self.tokens.splice(self.pos..self.pos, body);
+        Ok(())
+    }
+
+    //Octo evaluates :calc right to left, with no operator precedence
+    fn calc(&self, at: &Token, body: &[Token]) -> Result<f64> {
+        let (value, rest) = self.calc_expr(at, body)?;
+        match rest.first() {
+            None => Ok(value),
+            Some(tok) => Err(tok.unexpected("end of expression")),
+        }
+    }
+
+    fn calc_expr<'a>(&self, at: &Token, body: &'a [Token]) -> Result<(f64, &'a [Token])> {
+        let (lhs, rest) = self.calc_term(at, body)?;
+        let Some(op) = rest.first().filter(|tok| tok.text != ")") else {
+            return Ok((lhs, rest));
+        };
+
+        let (rhs, rest) = self.calc_expr(op, &rest[1..])?;
+        let int = |value: f64| value as i64;
+        let value = match op.text.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" if rhs == 0.0 => return Err(op.error(AsmErrorKind::DivideByZero)),
+            "/" => lhs / rhs,
+            "%" if int(rhs) == 0 => return Err(op.error(AsmErrorKind::DivideByZero)),
+            "%" => (int(lhs) % int(rhs)) as f64,
+            "&" => (int(lhs) & int(rhs)) as f64,
+            "|" => (int(lhs) | int(rhs)) as f64,
+            "^" => (int(lhs) ^ int(rhs)) as f64,
+            "<<" => int(lhs).wrapping_shl(int(rhs) as u32) as f64,
+            ">>" => int(lhs).wrapping_shr(int(rhs) as u32) as f64,
+            "pow" => lhs.powf(rhs),
+            "min" => lhs.min(rhs),
+            "max" => lhs.max(rhs),
+            _ => return Err(op.unexpected("an operator")),
+        };
+        Ok((value, rest))
+    }
+
+    fn calc_term<'a>(&self, at: &Token, body: &'a [Token]) -> Result<(f64, &'a [Token])> {
+        let Some((tok, rest)) = body.split_first() else {
+            return Err(at.unexpected("an expression after it"));
+        };
+
+        match tok.text.as_str() {
+            "(" => {
+                let (value, rest) = self.calc_expr(tok, rest)?;
+                match rest.split_first() {
+                    Some((close, rest)) if close.text == ")" => Ok((value, rest)),
+                    _ => Err(tok.unexpected("a matching )")),
+                }
+            },
+            "-" => self.calc_term(tok, rest).map(|(value, rest)| (-value, rest)),
+            "~" => self.calc_term(tok, rest).map(|(value, rest)| (!(value as i64) as f64, rest)),
+            "!" => self.calc_term(tok, rest).map(|(value, rest)| ((value == 0.0) as u8 as f64, rest)),
+            "HERE" => Ok((self.here() as f64, rest)),
+            _ => match self.symbols.get(&tok.text) {
+                Some(Symbol::Const(value)) => Ok((*value, rest)),
+                _ => Ok((self.value_of(tok)? as f64, rest)),
+            },
+        }
+    }
+
+    fn resolve_fixups(&mut self) -> Result<()> {
+        for fixup in std::mem::take(&mut self.fixups) {
+            let addr = match self.symbols.get(&fixup.tok.text) {
+                Some(Symbol::Label(addr)) => *addr,
+                _ => return Err(fixup.tok.error(AsmErrorKind::UndefinedLabel(fixup.tok.text.clone()))),
+            };
+            if !matches!(fixup.kind, FixupKind::LoadILong) && addr > 0xFFF {
+                return Err(fixup.tok.error(AsmErrorKind::OutOfRange { value: addr as i64, min: 0, max: 0xFFF }));
+            }
+
+            let bytes = address_instr(fixup.kind, addr).to_bytes();
+            self.bytes[fixup.offset..fixup.offset + bytes.len()].copy_from_slice(&bytes);
+        }
+        Ok(())
+    }
+}
+
+fn address_instr(kind: FixupKind, addr: u16) -> Instr {
+    match kind {
+        FixupKind::Jump => Instr::JP(u12::of(addr)),
+        FixupKind::Jump0 => Instr::JPL(u12::of(addr)),
+        FixupKind::Call => Instr::CALL(u12::of(addr)),
+        FixupKind::LoadI => Instr::LDI(u12::of(addr)),
+        FixupKind::LoadILong => Instr::LDIL(addr),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compiles() {
+        let src = "
+            :const SPEED 2
+            :alias x v1
+            :calc HALF { SPEED * ( 8 / 2 ) }
+            :macro bump reg amount { reg += amount }
+
+            : main
+                x := 0
+                i := face
+                loop
+                    sprite x v2 5
+                    bump x SPEED
+                    if x == 64 then x := 0
+                    while x != HALF
+                    if v3 key begin
+                        draw
+                    else
+                        v3 -= 1
+                    end
+                again
+                jump main
+
+            : draw  clear ;
+            : face  0xF0 0x90 :byte { HALF + 1 }
+        ";
+        let program = compile(src).unwrap();
+        assert_eq!(program.symbols["HALF"], Symbol::Const(8.0));
+        assert_eq!(program.symbols["x"], Symbol::Alias(GPReg::V1));
+        assert_eq!(program.symbols["draw"], Symbol::Label(0x220));
+        assert_eq!(program.bytes, [
+            0x12, 0x02, //to main
+            0x61, 0x00, //x := 0
+            0xA2, 0x24, //i := face
+            0xD1, 0x25, //loop: sprite x v2 5
+            0x71, 0x02, //x += SPEED
+            0x41, 0x40, //if x == 64 then
+            0x61, 0x00, //x := 0
+            0x41, 0x08, //while x != HALF
+            0x12, 0x1E, //  break out of the loop
+            0xE3, 0x9E, //if v3 key begin
+            0x12, 0x1A, //  to else
+            0x22, 0x20, //draw
+            0x12, 0x1C, //  past else
+            0x73, 0xFF, //v3 -= 1
+            0x12, 0x06, //again
+            0x12, 0x02, //jump main
+            0x00, 0xE0, 0x00, 0xEE, //draw
+            0xF0, 0x90, 0x09, //face
+        ]);
+    }
+
+    #[test]
+    fn main_after_subroutines() {
+        let src = "
+            : double  v0 += v0 ;
+            : main
+                v0 := 3
+                double
+                loop again
+        ";
+        let program = compile(src).unwrap();
+        assert_eq!(program.symbols["main"], Symbol::Label(0x206));
+        assert_eq!(program.bytes, [
+            0x12, 0x06, //to main
+            0x80, 0x04, 0x00, 0xEE, //double
+            0x60, 0x03, //v0 := 3
+            0x22, 0x02, //double
+            0x12, 0x0A, //loop again
+        ]);
+    }
+
+    #[test]
+    fn comparisons() {
+        let src = "
+            : main
+                if v1 < 5 then v2 := 1
+                if v1 >= v3 then v2 := 2
+                loop
+                    v1 += 1
+                    while v1 <= 9
+                    if v1 > v3 begin v2 := 3 end
+                again
+        ";
+        assert_eq!(compile(src).unwrap().bytes, [
+            0x12, 0x02, //to main
+            0x6F, 0x05, 0x8F, 0x17, 0x3F, 0x01, //if v1 < 5 then, skipping when v1 >= 5
+            0x62, 0x01, //v2 := 1
+            0x8F, 0x30, 0x8F, 0x17, 0x3F, 0x00, //if v1 >= v3 then, skipping when v1 < v3
+            0x62, 0x02, //v2 := 2
+            0x71, 0x01, //loop: v1 += 1
+            0x6F, 0x09, 0x8F, 0x15, 0x3F, 0x01, //while v1 <= 9, skipping the break when v1 <= 9
+            0x12, 0x28, //  break out of the loop
+            0x8F, 0x30, 0x8F, 0x15, 0x3F, 0x00, //if v1 > v3 begin, skipping the jump when v1 > v3
+            0x12, 0x26, //  past end
+            0x62, 0x03, //v2 := 3
+            0x12, 0x12, //again
+        ]);
+    }
+
+    #[test]
+    fn errors() {
+        let error = |src: &str| compile(&format!("{src}\n: main")).unwrap_err();
+        assert_eq!(compile("v0 := 1").unwrap_err(), AsmError { line: 1, col: 1, kind: AsmErrorKind::UndefinedLabel("main".into()) });
+        assert_eq!(error("jump nowhere"), AsmError { line: 1, col: 6, kind: AsmErrorKind::UndefinedLabel("nowhere".into()) });
+        assert_eq!(error(": a\n: a").kind, AsmErrorKind::DuplicateLabel("a".into()));
+        assert_eq!(error("v0 := 300").kind, AsmErrorKind::OutOfRange { value: 300, min: -0x80, max: 0xFF });
+        assert!(matches!(error("loop v0 := 1").kind, AsmErrorKind::Expected { expected: "again", .. }));
+        assert_eq!(error(":byte { 200 + 100 }").kind, AsmErrorKind::OutOfRange { value: 300, min: -0x80, max: 0xFF });
+
+        //A loop that starts past 0xFFF can't be jumped back to
+        let far = format!(": main\n{}\nloop again", "0 ".repeat(0x1000));
+        assert_eq!(compile(&far).unwrap_err().kind, AsmErrorKind::OutOfRange { value: 0x1202, min: 0, max: 0xFFF });
+    }
+}
\ No newline at end of file
@@ -0,0 +1,27 @@
+use crate::instructions::Instr;
+
+/// What `Instr::decode_with` does with opcodes that aren't defined in any
+/// supported instruction set, like 5xy1, 8xyF or Ex00
+#[derive(Copy, Clone, Debug, Default)]
+pub enum Undefined {
+    /// Fail with a decode error
+    #[default]
+    Reject,
+    /// Decode as `Instr::NOP`, which does nothing
+    Nop,
+    /// Let a variant give the opcode its own meaning. `None` rejects it.
+    Map(fn(u16) -> Option<Instr>),
+}
+
+/// Decoder settings for a CHIP-8 dialect
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DecodeOptions {
+    pub undefined: Undefined,
+}
+
+impl DecodeOptions {
+    /// Reject undefined opcodes. This is what `Instr::decode` does.
+    pub const STRICT: DecodeOptions = DecodeOptions { undefined: Undefined::Reject };
+    /// Treat undefined opcodes as no-ops
+    pub const LENIENT: DecodeOptions = DecodeOptions { undefined: Undefined::Nop };
+}
\ No newline at end of file
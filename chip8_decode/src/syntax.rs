@@ -0,0 +1,399 @@
+use std::fmt;
+use std::str::FromStr;
+
+use shared::reg::GPReg;
+
+use crate::instructions::Instr;
+
+/// Assembly syntax used when printing instructions
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug, Default)]
+pub enum Syntax {
+    /// The mnemonics from Cowgod's Chip-8 technical reference, e.g. `LD V3, 0x0C`
+    #[default]
+    Cowgod,
+    /// Octo assembly, e.g. `v3 := 0x0C`
+    Octo,
+}
+
+impl Syntax {
+    pub fn format(&self, instr: &Instr) -> String {
+        self.format_labeled(instr, None)
+    }
+
+    /// Like `format`, but writes `label` in place of the address operand
+    pub fn format_labeled(&self, instr: &Instr, label: Option<&str>) -> String {
+        match self {
+            Syntax::Cowgod => cowgod(instr, label),
+            Syntax::Octo => octo(instr, label),
+        }
+    }
+
+    /// A label definition on its own line
+    pub fn label(&self, name: &str) -> String {
+        match self {
+            Syntax::Cowgod => format!("{name}:"),
+            Syntax::Octo => format!(": {name}"),
+        }
+    }
+
+    pub fn comment(&self, text: &str) -> String {
+        match self {
+            Syntax::Cowgod => format!("; {text}"),
+            Syntax::Octo => format!("# {text}"),
+        }
+    }
+
+    /// Bytes that aren't (or can't be) disassembled
+    pub fn data(&self, bytes: &[u8]) -> String {
+        let bytes: Vec<String> = bytes.iter().map(|byte| format!("0x{byte:02X}")).collect();
+        match self {
+            Syntax::Cowgod => format!("DB {}", bytes.join(", ")),
+            Syntax::Octo => bytes.join(" "),
+        }
+    }
+}
+
+impl FromStr for Syntax {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_ref() {
+            "cowgod" => Ok(Syntax::Cowgod),
+            "octo" => Ok(Syntax::Octo),
+            _ => Err(format!("Unknown syntax \"{s}\", expected cowgod or octo")),
+        }
+    }
+}
+
+/// The first word of an instruction's Cowgod disassembly, e.g. `LD` for every one of
+/// its 14 forms. Parses case-insensitively from the same text it prints.
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Mnemonic {
+    SYS, CLS, RET, JP, CALL, SE, SNE, LD, ADD, OR, AND, XOR, SUB, SHR, SUBN, SHL, RND, DRW,
+    SKP, SKNP, SCD, SCR, SCL, EXIT, LOW, HIGH, SCU, PLANE, AUDIO, PITCH,
+    /// Words that only decode leniently, printed as data
+    DW,
+}
+
+impl Mnemonic {
+    pub const ALL: [Mnemonic; 31] = {
+        use Mnemonic::*;
+        [
+            SYS, CLS, RET, JP, CALL, SE, SNE, LD, ADD, OR, AND, XOR, SUB, SHR, SUBN, SHL, RND, DRW,
+            SKP, SKNP, SCD, SCR, SCL, EXIT, LOW, HIGH, SCU, PLANE, AUDIO, PITCH, DW,
+        ]
+    };
+
+    pub fn name(&self) -> &'static str {
+        use Mnemonic::*;
+        match self {
+            SYS => "SYS",
+            CLS => "CLS",
+            RET => "RET",
+            JP => "JP",
+            CALL => "CALL",
+            SE => "SE",
+            SNE => "SNE",
+            LD => "LD",
+            ADD => "ADD",
+            OR => "OR",
+            AND => "AND",
+            XOR => "XOR",
+            SUB => "SUB",
+            SHR => "SHR",
+            SUBN => "SUBN",
+            SHL => "SHL",
+            RND => "RND",
+            DRW => "DRW",
+            SKP => "SKP",
+            SKNP => "SKNP",
+            SCD => "SCD",
+            SCR => "SCR",
+            SCL => "SCL",
+            EXIT => "EXIT",
+            LOW => "LOW",
+            HIGH => "HIGH",
+            SCU => "SCU",
+            PLANE => "PLANE",
+            AUDIO => "AUDIO",
+            PITCH => "PITCH",
+            DW => "DW",
+        }
+    }
+}
+
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for Mnemonic {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Mnemonic::ALL.into_iter()
+            .find(|mnemonic| mnemonic.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("Unknown mnemonic \"{s}\""))
+    }
+}
+
+impl Instr {
+    pub fn mnemonic(&self) -> Mnemonic {
+        use Instr::*;
+        match self {
+            SYS(_) => Mnemonic::SYS,
+            CLS => Mnemonic::CLS,
+            RET => Mnemonic::RET,
+            JP(_) | JPL(_) => Mnemonic::JP,
+            CALL(_) => Mnemonic::CALL,
+            SEQ(..) | SE(..) => Mnemonic::SE,
+            SNELIT(..) | SNE(..) => Mnemonic::SNE,
+            LDL(..) | LD(..) | LDI(_) | MOVDT(_) | LDKB(_) | LDDT(_) | LDST(_) | LDSPR(_) | LDBCD(_)
+            | PUSHREG(_) | POPREG(_) | LDHSPR(_) | PUSHRPL(_) | POPRPL(_) | PUSHRANGE(..)
+            | POPRANGE(..) | LDIL(_) => Mnemonic::LD,
+            ADDL(..) | ADDC(..) | ADDI(_) => Mnemonic::ADD,
+            OR(..) => Mnemonic::OR,
+            AND(..) => Mnemonic::AND,
+            XOR(..) => Mnemonic::XOR,
+            SUBC(..) => Mnemonic::SUB,
+            SHRC(..) => Mnemonic::SHR,
+            SUBN(..) => Mnemonic::SUBN,
+            SHLC(..) => Mnemonic::SHL,
+            RND(..) => Mnemonic::RND,
+            DRW(..) => Mnemonic::DRW,
+            SKP(_) => Mnemonic::SKP,
+            SKNP(_) => Mnemonic::SKNP,
+            SCD(_) => Mnemonic::SCD,
+            SCR => Mnemonic::SCR,
+            SCL => Mnemonic::SCL,
+            EXIT => Mnemonic::EXIT,
+            LOW => Mnemonic::LOW,
+            HIGH => Mnemonic::HIGH,
+            SCU(_) => Mnemonic::SCU,
+            PLANE(_) => Mnemonic::PLANE,
+            AUDIO => Mnemonic::AUDIO,
+            PITCH(_) => Mnemonic::PITCH,
+            NOP(_) => Mnemonic::DW,
+        }
+    }
+}
+
+/// Formats listing lines: an optional address column, an optional raw
+/// opcode column, then the instruction in the chosen syntax.
+#[derive(Copy, Clone, Debug)]
+pub struct Formatter {
+    pub syntax: Syntax,
+    pub addresses: bool,
+    pub opcodes: bool,
+}
+
+impl Formatter {
+    pub fn new(syntax: Syntax) -> Self {
+        Formatter {
+            syntax,
+            addresses: true,
+            opcodes: true,
+        }
+    }
+
+    pub fn instr(&self, addr: u16, instr: &Instr) -> String {
+        self.instr_labeled(addr, instr, None)
+    }
+
+    pub fn instr_labeled(&self, addr: u16, instr: &Instr, label: Option<&str>) -> String {
+        self.line(addr, &instr.to_bytes(), &self.syntax.format_labeled(instr, label))
+    }
+
+    pub fn data(&self, addr: u16, bytes: &[u8]) -> String {
+        self.line(addr, bytes, &self.syntax.data(bytes))
+    }
+
+    fn line(&self, addr: u16, bytes: &[u8], text: &str) -> String {
+        let mut line = String::new();
+        if self.addresses {
+            line.push_str(&format!("0x{addr:04X}  "));
+        }
+        if self.opcodes {
+            let words: Vec<String> = bytes.chunks(2)
+                .map(|word| word.iter().map(|byte| format!("{byte:02X}")).collect())
+                .collect();
+            //Room for the 4-byte F000 nnnn so the text column lines up
+            line.push_str(&format!("{:<11}", words.join(" ")));
+        }
+        line.push_str(text);
+        line
+    }
+}
+
+impl fmt::Display for Instr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&cowgod(self, None))
+    }
+}
+
+//An address operand, or the label standing in for it
+fn target(addr: u16, digits: usize, label: Option<&str>) -> String {
+    match label {
+        Some(label) => label.to_owned(),
+        None => format!("0x{addr:0digits$X}"),
+    }
+}
+
+fn cowgod(instr: &Instr, label: Option<&str>) -> String {
+    let v = |reg: GPReg| format!("V{:X}", reg.to_idx());
+
+    use Instr::*;
+    match *instr {
+        SYS(addr) => format!("SYS {}", target(*addr, 3, label)),
+        CLS => "CLS".into(),
+        RET => "RET".into(),
+        JP(addr) => format!("JP {}", target(*addr, 3, label)),
+        CALL(addr) => format!("CALL {}", target(*addr, 3, label)),
+        SEQ(vx, byte) => format!("SE {}, 0x{byte:02X}", v(vx)),
+        SNELIT(vx, byte) => format!("SNE {}, 0x{byte:02X}", v(vx)),
+        SE(vx, vy) => format!("SE {}, {}", v(vx), v(vy)),
+        LDL(vx, byte) => format!("LD {}, 0x{byte:02X}", v(vx)),
+        ADDL(vx, byte) => format!("ADD {}, 0x{byte:02X}", v(vx)),
+        LD(vx, vy) => format!("LD {}, {}", v(vx), v(vy)),
+        OR(vx, vy) => format!("OR {}, {}", v(vx), v(vy)),
+        AND(vx, vy) => format!("AND {}, {}", v(vx), v(vy)),
+        XOR(vx, vy) => format!("XOR {}, {}", v(vx), v(vy)),
+        ADDC(vx, vy) => format!("ADD {}, {}", v(vx), v(vy)),
+        SUBC(vx, vy) => format!("SUB {}, {}", v(vx), v(vy)),
+        SHRC(vx, vy) => format!("SHR {}, {}", v(vx), v(vy)),
+        SUBN(vx, vy) => format!("SUBN {}, {}", v(vx), v(vy)),
+        SHLC(vx, vy) => format!("SHL {}, {}", v(vx), v(vy)),
+        SNE(vx, vy) => format!("SNE {}, {}", v(vx), v(vy)),
+        LDI(addr) => format!("LD I, {}", target(*addr, 3, label)),
+        JPL(addr) => format!("JP V0, {}", target(*addr, 3, label)),
+        RND(vx, byte) => format!("RND {}, 0x{byte:02X}", v(vx)),
+        DRW(vx, vy, n) => format!("DRW {}, {}, {}", v(vx), v(vy), *n),
+        SKP(vx) => format!("SKP {}", v(vx)),
+        SKNP(vx) => format!("SKNP {}", v(vx)),
+        MOVDT(vx) => format!("LD {}, DT", v(vx)),
+        LDKB(vx) => format!("LD {}, K", v(vx)),
+        LDDT(vx) => format!("LD DT, {}", v(vx)),
+        LDST(vx) => format!("LD ST, {}", v(vx)),
+        ADDI(vx) => format!("ADD I, {}", v(vx)),
+        LDSPR(vx) => format!("LD F, {}", v(vx)),
+        LDBCD(vx) => format!("LD B, {}", v(vx)),
+        PUSHREG(vx) => format!("LD [I], {}", v(vx)),
+        POPREG(vx) => format!("LD {}, [I]", v(vx)),
+        SCD(n) => format!("SCD {}", *n),
+        SCR => "SCR".into(),
+        SCL => "SCL".into(),
+        EXIT => "EXIT".into(),
+        LOW => "LOW".into(),
+        HIGH => "HIGH".into(),
+        LDHSPR(vx) => format!("LD HF, {}", v(vx)),
+        PUSHRPL(vx) => format!("LD R, {}", v(vx)),
+        POPRPL(vx) => format!("LD {}, R", v(vx)),
+        SCU(n) => format!("SCU {}", *n),
+        PUSHRANGE(vx, vy) => format!("LD [I], {} - {}", v(vx), v(vy)),
+        POPRANGE(vx, vy) => format!("LD {} - {}, [I]", v(vx), v(vy)),
+        LDIL(addr) => format!("LD I, LONG {}", target(addr, 4, label)),
+        PLANE(n) => format!("PLANE {}", *n),
+        AUDIO => "AUDIO".into(),
+        PITCH(vx) => format!("PITCH {}", v(vx)),
+        //No mnemonic, but reassembles to the same word
+        NOP(opcode) => format!("DW 0x{opcode:04X}"),
+    }
+}
+
+fn octo(instr: &Instr, label: Option<&str>) -> String {
+    let v = |reg: GPReg| format!("v{:x}", reg.to_idx());
+
+    use Instr::*;
+    match *instr {
+        //Octo has no SYS, so emit the raw bytes
+        SYS(addr) => format!("0x{:02X} 0x{:02X}", *addr >> 8, *addr & 0xFF),
+        CLS => "clear".into(),
+        RET => "return".into(),
+        JP(addr) => format!("jump {}", target(*addr, 3, label)),
+        CALL(addr) => format!(":call {}", target(*addr, 3, label)),
+        //Octo conditions say when the next instruction runs, so skips are inverted
+        SEQ(vx, byte) => format!("if {} != 0x{byte:02X} then", v(vx)),
+        SNELIT(vx, byte) => format!("if {} == 0x{byte:02X} then", v(vx)),
+        SE(vx, vy) => format!("if {} != {} then", v(vx), v(vy)),
+        LDL(vx, byte) => format!("{} := 0x{byte:02X}", v(vx)),
+        ADDL(vx, byte) => format!("{} += 0x{byte:02X}", v(vx)),
+        LD(vx, vy) => format!("{} := {}", v(vx), v(vy)),
+        OR(vx, vy) => format!("{} |= {}", v(vx), v(vy)),
+        AND(vx, vy) => format!("{} &= {}", v(vx), v(vy)),
+        XOR(vx, vy) => format!("{} ^= {}", v(vx), v(vy)),
+        ADDC(vx, vy) => format!("{} += {}", v(vx), v(vy)),
+        SUBC(vx, vy) => format!("{} -= {}", v(vx), v(vy)),
+        SHRC(vx, vy) => format!("{} >>= {}", v(vx), v(vy)),
+        SUBN(vx, vy) => format!("{} =- {}", v(vx), v(vy)),
+        SHLC(vx, vy) => format!("{} <<= {}", v(vx), v(vy)),
+        SNE(vx, vy) => format!("if {} == {} then", v(vx), v(vy)),
+        LDI(addr) => format!("i := {}", target(*addr, 3, label)),
+        JPL(addr) => format!("jump0 {}", target(*addr, 3, label)),
+        RND(vx, byte) => format!("{} := random 0x{byte:02X}", v(vx)),
+        DRW(vx, vy, n) => format!("sprite {} {} {}", v(vx), v(vy), *n),
+        SKP(vx) => format!("if {} -key then", v(vx)),
+        SKNP(vx) => format!("if {} key then", v(vx)),
+        MOVDT(vx) => format!("{} := delay", v(vx)),
+        LDKB(vx) => format!("{} := key", v(vx)),
+        LDDT(vx) => format!("delay := {}", v(vx)),
+        LDST(vx) => format!("buzzer := {}", v(vx)),
+        ADDI(vx) => format!("i += {}", v(vx)),
+        LDSPR(vx) => format!("i := hex {}", v(vx)),
+        LDBCD(vx) => format!("bcd {}", v(vx)),
+        PUSHREG(vx) => format!("save {}", v(vx)),
+        POPREG(vx) => format!("load {}", v(vx)),
+        SCD(n) => format!("scroll-down {}", *n),
+        SCR => "scroll-right".into(),
+        SCL => "scroll-left".into(),
+        EXIT => "exit".into(),
+        LOW => "lores".into(),
+        HIGH => "hires".into(),
+        LDHSPR(vx) => format!("i := bighex {}", v(vx)),
+        PUSHRPL(vx) => format!("saveflags {}", v(vx)),
+        POPRPL(vx) => format!("loadflags {}", v(vx)),
+        SCU(n) => format!("scroll-up {}", *n),
+        PUSHRANGE(vx, vy) => format!("save {} - {}", v(vx), v(vy)),
+        POPRANGE(vx, vy) => format!("load {} - {}", v(vx), v(vy)),
+        LDIL(addr) => format!("i := long {}", target(addr, 4, label)),
+        PLANE(n) => format!("plane {}", *n),
+        AUDIO => "audio".into(),
+        PITCH(vx) => format!("pitch := {}", v(vx)),
+        NOP(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listing() {
+        let ld = Instr::decode(0x630C).unwrap();
+        assert_eq!(ld.to_string(), "LD V3, 0x0C");
+        assert_eq!(Syntax::Octo.format(&ld), "v3 := 0x0C");
+        assert_eq!(Syntax::Octo.format(&Instr::decode(0x3A01).unwrap()), "if va != 0x01 then");
+
+        let formatter = Formatter::new(Syntax::Cowgod);
+        assert_eq!(formatter.instr(0x200, &ld), "0x0200  630C       LD V3, 0x0C");
+        assert_eq!(formatter.instr(0x202, &Instr::LDIL(0x1234)), "0x0202  F000 1234  LD I, LONG 0x1234");
+        assert_eq!(formatter.data(0x206, &[0xAB]), "0x0206  AB         DB 0xAB");
+    }
+
+    #[test]
+    fn mnemonics() {
+        let options = crate::options::DecodeOptions::LENIENT;
+        for opcode in 0..=u16::MAX {
+            let instr = match opcode {
+                0xF000 => Instr::LDIL(0x1234),
+                _ => Instr::decode_with(opcode, &options).unwrap(),
+            };
+            let text = instr.to_string();
+            assert_eq!(text.split_whitespace().next(), Some(instr.mnemonic().name()), "{instr:?}");
+        }
+        for mnemonic in Mnemonic::ALL {
+            assert_eq!(mnemonic.name().to_lowercase().parse(), Ok(mnemonic));
+        }
+        assert!("LDL".parse::<Mnemonic>().is_err());
+    }
+}
\ No newline at end of file
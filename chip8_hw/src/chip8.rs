@@ -1,43 +1,92 @@
 pub(crate) mod font;
 pub(crate) mod timers;
 pub(crate) mod quirks;
+pub(crate) mod instr_set;
+pub(crate) mod audio;
+pub(crate) mod display;
+pub(crate) mod rng;
+pub(crate) mod memory;
+pub(crate) mod platform;
+pub(crate) mod savestate;
+pub(crate) mod rewind;
 pub mod keyboard;
 
-pub use quirks::{QUIRKS_NEW, QUIRKS_OLD};
+pub use quirks::Quirks;
+pub use platform::Platform;
+pub use savestate::STATE_VERSION;
+pub use instr_set::InstrSet;
+pub use display::Display;
+pub use timers::WallClock;
+pub use rng::Rng;
+pub use memory::MemoryPolicy;
 
-use chip8_decode::instructions::Instr;
-use shared::{numtypes::u12, reg::GPReg};
+use std::convert::Infallible;
 
-use self::{font::FONT, keyboard::{Key, Keyboard}, quirks::Quirks, timers::Timers};
+use chip8_decode::{flow::Flow, instructions::Instr, options::{DecodeOptions, Undefined}};
+use shared::reg::GPReg;
+
+use crate::{debug::{Access, MemAccess}, Error, Result, RomError};
+
+use self::{audio::Audio, font::{BIG_FONT, FONT}, keyboard::{Key, Keyboard}, rewind::Rewind, timers::Timers};
 
 pub const RAM_SIZE: usize = 0x1000;
-pub const ROM_MAX_SIZE: usize = 0xE00;
+pub const XO_RAM_SIZE: usize = 0x10000;
 pub const STACK_LIMIT: usize = 0x10;
+pub const FONT_ADDR: u16 = 0x0;
+pub const BIG_FONT_ADDR: u16 = 0x50;
 
 pub const VRAM_WIDTH: usize = 64;
 pub const VRAM_HEIGHT: usize = 32;
 pub const VRAM_WH: usize = 64 * 32;
 
-#[derive(Debug)]
+pub const HIRES_WIDTH: usize = 128;
+pub const HIRES_HEIGHT: usize = 64;
+pub const HIRES_WH: usize = 128 * 64;
+pub const PLANES: usize = 4;
+
+/// What a `run_frame_with` hook did with the machine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum FrameStep<B> {
+    /// Executed this instruction
+    Ran(Instr),
+    /// Stop the frame, after executing the instruction if there is one, and have
+    /// `run_frame_with` return the value
+    Stop(Option<Instr>, B),
+}
+
+#[derive(Debug, Clone)]
 pub struct Chip8 {
-    pub ram: [u8; RAM_SIZE],
+    pub ram: Vec<u8>,
     pub gpregs: [u8; 0x10],
-    pub i_reg: u12,
+    pub i_reg: u16,
     pub pc: u16,
     pub sp: usize,
     pub stack: [u16; STACK_LIMIT],
-    pub vram: [bool; VRAM_WH],
+    pub display: Display,
+    pub rpl: [u8; 0x10],
     pub keyboard: Keyboard,
     halted: bool,
+    ipf: usize,
+    //Instructions run so far in the current frame, so a stopped frame can carry on
+    frame_pos: usize,
+    drawn_this_frame: bool,
+    mem_policy: MemoryPolicy,
+    platform: Platform,
     quirks: Quirks,
+    decode: DecodeOptions,
+    instr_set: InstrSet,
+    accesses: Vec<MemAccess>,
+    rewind: Option<Box<Rewind>>,
     pub timers: Timers,
+    pub audio: Audio,
+    pub rng: Rng,
 }
 
 impl Chip8 {
-    fn copy_font(ram: &mut [u8]) {
-        assert!(ram.len() <= 5 * 0x10);
+    fn copy_font<const N: usize>(ram: &mut [u8], font: &[[u8; N]; 0x10]) {
+        assert!(ram.len() <= N * 0x10);
 
-        FONT.iter()
+        font.iter()
             .flat_map(IntoIterator::into_iter)
             .enumerate()
             .for_each(|(idx, &byte)| ram[idx] = byte);
@@ -51,72 +100,294 @@ impl Chip8 {
         self.halted = halt;
     }
 
-    pub fn pixel_on(&self, idx: usize) -> bool {
-        self.vram[idx]
+    pub fn platform(&self) -> Platform {
+        self.platform
     }
 
-    pub fn load_rom(quirks: Quirks, rom: &[u8]) -> Self {
-        assert!(rom.len() < ROM_MAX_SIZE, "ROM is too large! Must be at most {ROM_MAX_SIZE} bytes!");
+    pub fn instr_set(&self) -> InstrSet {
+        self.instr_set
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        self.quirks
+    }
+
+    /// Override the platform's quirks, e.g. to run a ROM that expects a mix of behaviors
+    pub fn set_quirks(&mut self, quirks: Quirks) {
+        self.quirks = quirks;
+    }
+
+    pub fn decode_options(&self) -> DecodeOptions {
+        self.decode
+    }
+
+    /// Override how undefined opcodes are decoded, including those from instruction set
+    /// extensions the platform lacks. Savestates don't keep this, `load_state` restores
+    /// the platform's default.
+    pub fn set_decode_options(&mut self, options: DecodeOptions) {
+        self.decode = options;
+    }
+
+    pub fn memory_policy(&self) -> MemoryPolicy {
+        self.mem_policy
+    }
+
+    pub fn set_memory_policy(&mut self, policy: MemoryPolicy) {
+        self.mem_policy = policy;
+    }
+
+    /// How many instructions `run_frame` executes between timer ticks
+    pub fn instructions_per_frame(&self) -> usize {
+        self.ipf
+    }
+
+    pub fn set_instructions_per_frame(&mut self, ipf: usize) {
+        self.ipf = ipf;
+    }
+
+    //Whether the frame ends early after `instr`: an LD Vx, K that ran without a key leaves
+    //PC on itself, so the rest of the frame would only spin there, and with display wait
+    //a DRW waits for the vertical blank
+    fn ends_frame(&self, instr: &Instr, next_key: Option<Key>) -> bool {
+        match instr {
+            Instr::LDKB(_) => next_key.is_none(),
+            Instr::DRW(..) => self.quirks.display_wait,
+            _ => false,
+        }
+    }
+
+    /// Count DT and ST down by one. The host calls this at 60 Hz.
+    pub fn tick_timers(&mut self) {
+        let before = self.before_tick();
+        self.timers.tick();
+        self.drawn_this_frame = false;
+        self.frame_pos = 0;
+        self.after_tick(before);
+    }
+
+    /// Execute one 60 Hz frame: up to `instructions_per_frame` instructions, then
+    /// a timer tick. Stops early if the machine halts, waits for a key that
+    /// `next_key` doesn't give, or draws with the display wait quirk. Returns the last instruction executed, if any.
+    ///
+    /// If an instruction fails, the error is returned and the next call carries on
+    /// with the same frame.
+    pub fn run_frame(&mut self, next_key: Option<Key>) -> Result<Option<Instr>> {
+        let mut last = None;
+        self.run_frame_with(next_key, |c8| {
+            let instr = c8.step(next_key)?;
+            last = Some(instr);
+            Ok(FrameStep::<Infallible>::Ran(instr))
+        })?;
+        Ok(last)
+    }
+
+    /// Like `run_frame`, but each instruction is executed by `step` instead of
+    /// `Chip8::step`, so a tracer or debugger can wrap it. `step` can stop the frame
+    /// early with `FrameStep::Stop`, and the next call carries on with the same frame,
+    /// so the timers tick at the same point however a frame is split up.
+    pub fn run_frame_with<B>(&mut self, next_key: Option<Key>, mut step: impl FnMut(&mut Chip8) -> Result<FrameStep<B>>) -> Result<Option<B>> {
+        let mut stopped = None;
+        while stopped.is_none() && self.frame_pos < self.ipf && !self.halted {
+            let ran = match step(self)? {
+                FrameStep::Ran(instr) => Some(instr),
+                FrameStep::Stop(ran, value) => {
+                    stopped = Some(value);
+                    ran
+                },
+            };
+            if let Some(instr) = ran {
+                self.frame_pos += 1;
+                if self.ends_frame(&instr, next_key) {
+                    self.frame_pos = self.ipf;
+                }
+            }
+        }
+
+        if self.frame_pos >= self.ipf || self.halted {
+            self.tick_timers();
+        }
+        Ok(stopped)
+    }
+
+    /// A machine for `platform` with `rom` loaded at 0x200
+    pub fn load_rom(platform: Platform, rom: &[u8]) -> std::result::Result<Self, RomError> {
+        let instr_set = platform.instr_set();
+        let max = platform.ram_size() - 0x200;
+        if rom.len() > max {
+            return Err(RomError::TooLarge { size: rom.len(), max });
+        }
 
         let mut c8 = Self {
-            ram: [0x0; RAM_SIZE],
+            ram: vec![0x0; platform.ram_size()],
             gpregs: [0x0; 0x10],
-            i_reg: u12::of(0x0),
+            i_reg: 0x0,
             pc: 0x200,
             sp: 0x0,
             stack: [0x00; STACK_LIMIT],
-            vram: [false; VRAM_WH],
+            display: Display::new(instr_set.planes()),
+            rpl: [0x0; 0x10],
             keyboard: Keyboard::default(),
             halted: false,
-            quirks,
+            ipf: platform.instructions_per_frame(),
+            frame_pos: 0,
+            drawn_this_frame: false,
+            mem_policy: MemoryPolicy::default(),
+            platform,
+            quirks: platform.quirks(),
+            decode: platform.decode_options(),
+            instr_set,
+            accesses: Vec::new(),
+            rewind: None,
             timers: Timers::default(),
+            audio: Audio::default(),
+            rng: Rng::default(),
         };
 
-        Self::copy_font(&mut c8.ram[0..=0x4F]);
+        Self::copy_font(&mut c8.ram[FONT_ADDR as usize..BIG_FONT_ADDR as usize], &FONT);
+        Self::copy_font(&mut c8.ram[BIG_FONT_ADDR as usize..BIG_FONT_ADDR as usize + 0xA0], &BIG_FONT);
+
+        c8.ram[0x200..0x200 + rom.len()].copy_from_slice(rom);
+
+        Ok(c8)
+    }
+
+    /// Like `load_rom`, but RND is seeded with `seed` so runs can be replayed
+    pub fn load_rom_seeded(platform: Platform, rom: &[u8], seed: u64) -> std::result::Result<Self, RomError> {
+        Ok(Self { rng: Rng::seeded(seed), ..Self::load_rom(platform, rom)? })
+    }
+
+    //I is as wide as the address space: 12 bits normally, 16 bits on XO-CHIP
+    fn set_i(&mut self, addr: u16) {
+        self.i_reg = addr & (self.ram.len() - 1) as u16;
+    }
+
+    /// The bytes the last `step` read or wrote through I, in access order
+    pub fn accesses(&self) -> &[MemAccess] {
+        &self.accesses
+    }
+
+    //Read `len` bytes starting at `addr`, honoring the memory policy.
+    //On a fault, returns the first address that was out of bounds.
+    fn read_ram(&mut self, addr: usize, len: usize) -> std::result::Result<Vec<u8>, usize> {
+        let addrs = (addr..addr + len)
+            .map(|a| self.mem_policy.resolve(a, self.ram.len()).ok_or(a))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
 
-        for idx in 0..rom.len() {
-            c8.ram[idx + 0x200] = rom[idx];
+        self.accesses.extend(addrs.iter().map(|&a| MemAccess { kind: Access::Read, addr: a as u16 }));
+        Ok(addrs.into_iter().map(|a| self.ram[a]).collect())
+    }
+
+    //Write `bytes` starting at `addr`, honoring the memory policy.
+    //Nothing is written if any address faults.
+    fn write_ram(&mut self, addr: usize, bytes: &[u8]) -> std::result::Result<(), usize> {
+        let addrs = (addr..addr + bytes.len())
+            .map(|a| self.mem_policy.resolve(a, self.ram.len()).ok_or(a))
+            .collect::<std::result::Result<Vec<_>, _>>()?;
+
+        self.accesses.extend(addrs.iter().map(|&a| MemAccess { kind: Access::Write, addr: a as u16 }));
+        if let Some(rewind) = self.rewind.as_mut() {
+            rewind.overwritten.extend(addrs.iter().map(|&a| (a as u16, self.ram[a])));
         }
+        addrs.into_iter()
+            .zip(bytes)
+            .for_each(|(a, &byte)| self.ram[a] = byte);
+        Ok(())
+    }
 
-        c8
+    //SUPER-CHIP 1.1 scrolls by high resolution pixels even in low resolution
+    fn scroll(&mut self, dx: isize, dy: isize) {
+        let scale = if self.quirks.lores_scaled && !self.display.is_hires() { 2 } else { 1 };
+        self.display.scroll(dx / scale, dy / scale);
+    }
+
+    //Skip the next instruction. XO-CHIP skips over the whole of F000 nnnn.
+    fn skip(&mut self) {
+        let pc = self.pc as usize;
+        let len = match self.ram.get(pc..pc + 2) {
+            Some(&[hi, lo]) if self.instr_set.has_xochip() => Flow::skip_len(Some((hi as u16) << 8 | lo as u16)),
+            _ => 2,
+        };
+        self.pc = self.pc.wrapping_add(len);
     }
 
-    pub fn step(&mut self, next_key: Option<Key>) -> Result<Instr, String> {
-        if self.pc as usize >= RAM_SIZE - 1 {
-            return Err(format!("PC beyond RAM limit! pc = 0x{:04X}", self.pc));
+    /// Decode the instruction at PC without executing it. Fails like `step` would
+    /// before the instruction runs.
+    pub fn fetch(&self) -> Result<Instr> {
+        let pc = self.pc;
+        if pc as usize >= self.ram.len() - 1 {
+            return Err(Error::PcOutOfBounds { pc });
         }
 
-        let instr = {
-            let (b1, b2) = (self.ram[self.pc as usize], self.ram[(self.pc + 1) as usize]);
-            let bytes = (b1 as u16) << 8 | b2 as u16;
-            Instr::decode(bytes)
-        }.map_err(|e| format!("Failed to decode instruction: {e:#?}"))?;
+        let word = |addr: usize| (self.ram[addr] as u16) << 8 | self.ram[addr + 1] as u16;
+        let opcode = word(pc as usize);
+        let instr = if self.instr_set.has_xochip() && opcode == 0xF000 {
+            if pc as usize + 3 >= self.ram.len() {
+                return Err(Error::PcOutOfBounds { pc });
+            }
+            Instr::decode_long_with(opcode, word(pc as usize + 2), &self.decode)
+        } else {
+            Instr::decode_with(opcode, &self.decode)
+        }.map_err(|err| Error::Decode { pc, opcode, err: err.decode_err() })?;
 
-        self.pc += 2;
-        self.timers.tick();
+        if self.instr_set.supports(&instr) {
+            return Ok(instr);
+        }
+
+        //Opcodes from an extension this machine lacks are as undefined to it as 8xyF
+        let unsupported = Error::Unsupported { pc, opcode, instr, instr_set: self.instr_set };
+        match self.decode.undefined {
+            Undefined::Reject => Err(unsupported),
+            Undefined::Nop => Ok(Instr::NOP(opcode)),
+            Undefined::Map(map) => map(opcode).ok_or(unsupported),
+        }
+    }
+
+    pub fn step(&mut self, next_key: Option<Key>) -> Result<Instr> {
+        self.accesses.clear();
+        let instr = self.fetch()?;
+        let before = self.before_step(&instr);
+
+        //A failed instruction leaves PC on itself, so the error's pc is where execution resumes
+        let pc = self.pc;
+        let result = self.execute(instr, next_key);
+        match (before, &result) {
+            (Some(before), Ok(_)) => self.after_step(before),
+            (_, Err(_)) => self.pc = pc,
+            (None, Ok(_)) => {},
+        }
+        result
+    }
+
+    fn execute(&mut self, instr: Instr, next_key: Option<Key>) -> Result<Instr> {
+        let pc = self.pc;
+        let opcode = (self.ram[pc as usize] as u16) << 8 | self.ram[pc as usize + 1] as u16;
+
+        let fault = |addr| Error::MemoryFault { pc, opcode, addr };
+
+        self.pc = self.pc.wrapping_add(instr.byte_len());
         
         use chip8_decode::instructions::Instr::*;
         match instr {
             SYS(_) => {},
-            CLS => self.vram.fill(false),
+            CLS => self.display.clear(),
             RET => {
                 if self.sp == 0 {
-                    return Err(format!("Stack underflow! pc = 0x{:04X}", self.pc - 2));
+                    return Err(Error::StackUnderflow { pc, opcode });
                 }
 
-                self.stack[self.sp] = 0;
                 self.sp -= 1;
                 self.pc = self.stack[self.sp];
+                self.stack[self.sp] = 0;
             },
             JP(addr) => {
-                if self.pc - 2 == *addr {
+                if pc == *addr {
                     self.halted = true;
                 }
                 self.pc = *addr;
             },
             CALL(addr) => {
-                if self.sp as usize == STACK_LIMIT {
-                    return Err(format!("Stack overflow! pc = 0x{:04X}", self.pc - 2));
+                if self.sp == STACK_LIMIT {
+                    return Err(Error::StackOverflow { pc, opcode });
                 }
 
                 self.stack[self.sp] = self.pc;
@@ -125,17 +396,17 @@ impl Chip8 {
             },
             SEQ(vx, lit) => {
                 if self.gpregs[vx] == lit {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             SNELIT(vx, lit) => {
                 if self.gpregs[vx] != lit {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             SE(vx, vy) => {
                 if self.gpregs[vx] == self.gpregs[vy] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             LDL(vx, lit) => self.gpregs[vx] = lit,
@@ -145,19 +416,19 @@ impl Chip8 {
             },
             LD(vx, vy) => self.gpregs[vx] = self.gpregs[vy],
             OR(vx, vy) => {
-                self.gpregs[vx] = self.gpregs[vx] | self.gpregs[vy];
+                self.gpregs[vx] |= self.gpregs[vy];
                 if self.quirks.vf_reset {
                     self.gpregs[GPReg::VF] = 0;
                 }
             },
             AND(vx, vy) => {
-                self.gpregs[vx] = self.gpregs[vx] & self.gpregs[vy];
+                self.gpregs[vx] &= self.gpregs[vy];
                 if self.quirks.vf_reset {
                     self.gpregs[GPReg::VF] = 0;
                 }
             },
             XOR(vx, vy) => {
-                self.gpregs[vx] = self.gpregs[vx] ^ self.gpregs[vy];
+                self.gpregs[vx] ^= self.gpregs[vy];
                 if self.quirks.vf_reset {
                     self.gpregs[GPReg::VF] = 0;
                 }
@@ -203,68 +474,85 @@ impl Chip8 {
             },
             SNE(vx, vy) => {
                 if self.gpregs[vx] != self.gpregs[vy] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
-            LDI(addr) => self.i_reg = addr,
-            JPL(addr) => self.pc = self.gpregs[GPReg::V0] as u16 + *addr,
+            LDI(addr) => self.set_i(*addr),
+            JPL(addr) => {
+                let reg = if self.quirks.jumping {
+                    GPReg::indexed((*addr >> 8) as u8).unwrap_or(GPReg::V0)
+                } else {
+                    GPReg::V0
+                };
+                self.pc = self.gpregs[reg] as u16 + *addr;
+            },
             RND(vx, byte) => {
-                let rng = rand::random::<u8>() & byte;
+                let rng = self.rng.next_byte() & byte;
                 self.gpregs[vx] = rng;
             },
             DRW(vx, vy, size) => {
-                self.gpregs[GPReg::VF] = 0;
-
-                let x_start = self.gpregs[vx] as usize & 63;
-                let y_start = self.gpregs[vy] as usize & 31;
-
-                let spr = &self.ram[*self.i_reg as usize ..= (*self.i_reg + *size as u16) as usize];
-                for y in 0 .. *size {
-                    let byte = spr[y as usize];
-                    for x in 0 ..= 7 {
-                        let mask = 0b10000000 >> x;
-                        let bit = byte & mask == mask;
+                //With display wait, a second DRW in the same frame spins until the next timer tick
+                if self.quirks.display_wait && self.drawn_this_frame {
+                    self.pc = pc;
+                    return Ok(instr);
+                }
 
-                        let y_coord = y_start + y as usize;
-                        let x_coord = x_start + x as usize;
+                let x = self.gpregs[vx] as usize & (self.display.width() - 1);
+                let y = self.gpregs[vy] as usize & (self.display.height() - 1);
 
-                        if y_coord >= VRAM_HEIGHT || x_coord >= VRAM_WIDTH {
-                            continue;
-                        }
+                //SUPER-CHIP draws Dxy0 as a 16x16 sprite, two bytes per row
+                let (spr_width, spr_height) = match *size {
+                    0 if self.instr_set.has_schip() => (16, 16),
+                    n => (8, n as usize),
+                };
 
-                        let idx = y_coord * VRAM_WIDTH + x_coord;
-                        if self.vram[idx] {
-                            self.gpregs[GPReg::VF] = 1;
-                        }
+                //Each selected plane consumes its own copy of the sprite data, in plane order.
+                //All of it is read before drawing, so a fault leaves the display untouched.
+                let spr_len = spr_height * spr_width / 8;
+                let planes: Vec<u8> = self.display.selected_planes().collect();
+                let sprites = self.read_ram(self.i_reg as usize, spr_len * planes.len()).map_err(fault)?;
+                self.drawn_this_frame = true;
 
-                        self.vram[idx] ^= bit;
-                    }
+                let mut collided = false;
+                for (idx, plane) in planes.into_iter().enumerate() {
+                    let sprite = &sprites[idx * spr_len..(idx + 1) * spr_len];
+                    collided |= self.display.draw(plane, x, y, spr_width, sprite, !self.quirks.clipping);
                 }
+
+                self.gpregs[GPReg::VF] = collided as u8;
             },
             SKP(vx) => {
-                let key = Key::try_from(self.gpregs[vx]).map_err(|_| format!("Invalid key idx {}. pc = 0x{:4X}", self.gpregs[vx], self.pc))?;
+                let value = self.gpregs[vx];
+                let key = Key::try_from(value).map_err(|_| Error::InvalidKey { pc, opcode, reg: vx, value })?;
                 if self.keyboard[key] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             SKNP(vx) => {
-                let key = Key::try_from(self.gpregs[vx]).map_err(|_| format!("Invalid key idx {}. pc = 0x{:4X}", self.gpregs[vx], self.pc))?;
+                let value = self.gpregs[vx];
+                let key = Key::try_from(value).map_err(|_| Error::InvalidKey { pc, opcode, reg: vx, value })?;
                 if !self.keyboard[key] {
-                    self.pc += 2;
+                    self.skip();
                 }
             },
             MOVDT(vx) => self.gpregs[vx] = self.timers.dt,
             LDKB(vx) => {
                 let Some(key) = next_key else {
-                    self.pc -= 2;
+                    self.pc = pc;
                     return Ok(instr);
                 };
                 self.gpregs[vx] = key as u8;
             },
             LDDT(vx) => self.timers.dt = self.gpregs[vx],
             LDST(vx) => self.timers.st = self.gpregs[vx],
-            ADDI(vx) => self.i_reg.modify(|i| i + self.gpregs[vx] as u16),
-            LDSPR(vx) => self.i_reg.modify(|_| self.gpregs[vx] as u16 * 5),
+            ADDI(vx) => {
+                let sum = self.i_reg as usize + self.gpregs[vx] as usize;
+                self.set_i(sum as u16);
+                if self.quirks.i_overflow {
+                    self.gpregs[GPReg::VF] = (sum >= self.ram.len()) as u8;
+                }
+            },
+            LDSPR(vx) => self.set_i(FONT_ADDR + (self.gpregs[vx] & 0xF) as u16 * 5),
             LDBCD(vx) => {
                 let mut vx_val = self.gpregs[vx];
                 let ones = vx_val % 10;
@@ -273,32 +561,531 @@ impl Chip8 {
                 vx_val /= 10;
                 let hund = vx_val % 10;
 
-                self.ram[*self.i_reg as usize + 0] = hund;
-                self.ram[*self.i_reg as usize + 1] = tens;
-                self.ram[*self.i_reg as usize + 2] = ones;
+                self.write_ram(self.i_reg as usize, &[hund, tens, ones]).map_err(fault)?;
             },
             PUSHREG(vx) => {
-                for (i, addr) in (*self.i_reg ..= *self.i_reg + vx.to_idx() as u16).enumerate() {
-                    let reg = GPReg::indexed(i as u8).ok_or(format!("Invalid GPReg {}", i))?;
-                    self.ram[addr as usize] = self.gpregs[reg];
-                }
+                let regs = self.gpregs;
+                self.write_ram(self.i_reg as usize, &regs[..= vx.to_idx()]).map_err(fault)?;
 
                 if self.quirks.memory {
-                    self.i_reg.modify(|i| i + vx.to_idx() as u16 + 1);
+                    self.set_i(self.i_reg.wrapping_add(vx.to_idx() as u16 + 1));
                 }
             },
             POPREG(vx) => {
-                for (i, addr) in (*self.i_reg ..= *self.i_reg + vx.to_idx() as u16).enumerate() {
-                    let reg = GPReg::indexed(i as u8).ok_or(format!("Invalid GPReg {}", i))?;
-                    self.gpregs[reg] = self.ram[addr as usize];
-                }
+                let x = vx.to_idx();
+                let bytes = self.read_ram(self.i_reg as usize, x + 1).map_err(fault)?;
+                self.gpregs[..= x].copy_from_slice(&bytes);
 
                 if self.quirks.memory {
-                    self.i_reg.modify(|i| i + vx.to_idx() as u16 + 1);
+                    self.set_i(self.i_reg.wrapping_add(vx.to_idx() as u16 + 1));
+                }
+            },
+            SCD(n) => self.scroll(0, *n as isize),
+            SCR => self.scroll(4, 0),
+            SCL => self.scroll(-4, 0),
+            EXIT => self.halted = true,
+            LOW => self.display.set_hires(false, self.quirks.lores_scaled),
+            HIGH => self.display.set_hires(true, self.quirks.lores_scaled),
+            LDHSPR(vx) => self.set_i(BIG_FONT_ADDR + (self.gpregs[vx] & 0xF) as u16 * 10),
+            PUSHRPL(vx) => {
+                let count = vx.to_idx() + 1;
+                self.rpl[..count].copy_from_slice(&self.gpregs[..count]);
+            },
+            POPRPL(vx) => {
+                let count = vx.to_idx() + 1;
+                self.gpregs[..count].copy_from_slice(&self.rpl[..count]);
+            },
+            SCU(n) => self.scroll(0, -(*n as isize)),
+            PUSHRANGE(vx, vy) => {
+                let (x, y) = (vx.to_idx(), vy.to_idx());
+                let regs: Vec<usize> = if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() };
+                let bytes: Vec<u8> = regs.into_iter().map(|reg| self.gpregs[reg]).collect();
+                self.write_ram(self.i_reg as usize, &bytes).map_err(fault)?;
+            },
+            POPRANGE(vx, vy) => {
+                let (x, y) = (vx.to_idx(), vy.to_idx());
+                let regs: Vec<usize> = if x <= y { (x..=y).collect() } else { (y..=x).rev().collect() };
+                let bytes = self.read_ram(self.i_reg as usize, regs.len()).map_err(fault)?;
+                for (reg, byte) in regs.into_iter().zip(bytes) {
+                    self.gpregs[reg] = byte;
                 }
             },
+            LDIL(addr) => self.set_i(addr),
+            PLANE(n) => self.display.select_planes(*n),
+            AUDIO => {
+                let pattern = self.read_ram(self.i_reg as usize, 0x10).map_err(fault)?;
+                self.audio.pattern.copy_from_slice(&pattern);
+            },
+            PITCH(vx) => self.audio.pitch = self.gpregs[vx],
+            NOP(_) => {},
         }
 
         Ok(instr)
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ops::Range;
+
+    use super::*;
+
+    #[test]
+    fn memory_policy() {
+        //LD I, 0xFFE; LD B, V0
+        let rom = [0xAF, 0xFE, 0xF0, 0x33];
+
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_memory_policy(MemoryPolicy::Fault);
+        c8.step(None).unwrap();
+        let before = c8.clone();
+        assert_eq!(c8.step(None), Err(Error::MemoryFault { pc: 0x202, opcode: 0xF033, addr: 0x1000 }));
+        assert_eq!((c8.pc, c8.i_reg, c8.gpregs), (before.pc, before.i_reg, before.gpregs));
+        assert_eq!(c8.ram, before.ram);
+
+        //PLANE 3; DRW V0, V0, 1, where the first plane's sprite fits and the second's doesn't
+        let mut c8 = Chip8::load_rom(Platform::XoChip, &[0xF3, 0x01, 0xD0, 0x01]).unwrap();
+        c8.set_memory_policy(MemoryPolicy::Fault);
+        c8.i_reg = 0xFFFF;
+        c8.ram[0xFFFF] = 0xFF;
+        c8.step(None).unwrap();
+        let before = c8.clone();
+        assert_eq!(c8.step(None), Err(Error::MemoryFault { pc: 0x202, opcode: 0xD001, addr: 0x10000 }));
+        assert_eq!((c8.pc, c8.i_reg, c8.gpregs), (before.pc, before.i_reg, before.gpregs));
+        assert_eq!(c8.display.raw_pixels(), before.display.raw_pixels());
+        assert!(!c8.drawn_this_frame);
+
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.gpregs[GPReg::V0] = 123;
+        c8.step(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!((c8.ram[0xFFE], c8.ram[0xFFF], c8.ram[0x000]), (1, 2, 3));
+    }
+
+    #[test]
+    fn rom_size() {
+        assert!(Chip8::load_rom(Platform::Modern, &[0xFF; 0xE00]).is_ok());
+        assert_eq!(Chip8::load_rom(Platform::Modern, &[0xFF; 0xE01]).err(), Some(RomError::TooLarge { size: 0xE01, max: 0xE00 }));
+        assert!(Chip8::load_rom(Platform::XoChip, &[0xFF; 0xE01]).is_ok());
+        assert!(Chip8::load_rom_seeded(Platform::XoChip, &vec![0xFF; 0xFE01], 1).is_err());
+    }
+
+    #[test]
+    fn undefined_opcodes() {
+        //8xyF is undefined; LD V0, 0x05
+        let rom = [0x81, 0x2F, 0x60, 0x05];
+
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        assert!(matches!(c8.step(None), Err(Error::Decode { pc: 0x200, opcode: 0x812F, .. })));
+
+        let mut c8 = Chip8::load_rom(Platform::CosmacVip, &rom).unwrap();
+        assert_eq!(c8.step(None), Ok(Instr::NOP(0x812F)));
+        c8.step(None).unwrap();
+        assert_eq!(c8.gpregs[GPReg::V0], 5);
+
+        //A variant that gives 8xyF its own meaning
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_decode_options(DecodeOptions {
+            undefined: Undefined::Map(|opcode| (opcode & 0xF00F == 0x800F).then_some(Instr::CLS)),
+        });
+        assert_eq!(c8.step(None), Ok(Instr::CLS));
+    }
+
+    #[test]
+    fn unsupported_opcodes() {
+        //HIGH is SUPER-CHIP only; LD V0, 0x05
+        let rom = [0x00, 0xFF, 0x60, 0x05];
+
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        assert!(matches!(c8.step(None), Err(Error::Unsupported { pc: 0x200, opcode: 0x00FF, instr: Instr::HIGH, .. })));
+
+        //Lenient platforms skip them like any other undefined opcode
+        let mut c8 = Chip8::load_rom(Platform::CosmacVip, &rom).unwrap();
+        assert_eq!(c8.step(None), Ok(Instr::NOP(0x00FF)));
+        assert!(!c8.display.is_hires());
+        c8.step(None).unwrap();
+        assert_eq!(c8.gpregs[GPReg::V0], 5);
+
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_decode_options(DecodeOptions { undefined: Undefined::Map(|opcode| (opcode == 0x00FF).then_some(Instr::CLS)) });
+        assert_eq!(c8.step(None), Ok(Instr::CLS));
+    }
+
+    //Whether exactly the pixels `lit` picks are on
+    fn lit_where(display: &Display, lit: impl Fn(usize, usize) -> bool) -> bool {
+        (0..display.height()).all(|y| (0..display.width()).all(|x| display.pixel_on(x, y) == lit(x, y)))
+    }
+
+    fn lit_rect(display: &Display, xs: Range<usize>, ys: Range<usize>) -> bool {
+        lit_where(display, |x, y| xs.contains(&x) && ys.contains(&y))
+    }
+
+    #[test]
+    fn schip_display() {
+        //HIGH; DRW V0, V0, 0; SCD 4; SCR; SCL; DRW V0, V0, 0; LOW; SCR; EXIT
+        let rom = [
+            0x00, 0xFF, 0xD0, 0x00, 0x00, 0xC4, 0x00, 0xFB, 0x00, 0xFC, 0xD0, 0x00, 0x00, 0xFE, 0x00, 0xFB,
+            0x00, 0xFD,
+        ];
+        let mut c8 = Chip8::load_rom(Platform::SChip11, &rom).unwrap();
+        c8.i_reg = 0x300;
+        c8.ram[0x300..0x320].fill(0xFF);
+
+        c8.step(None).unwrap();
+        assert!(c8.display.is_hires());
+        assert_eq!((c8.display.width(), c8.display.height()), (HIRES_WIDTH, HIRES_HEIGHT));
+
+        //Dxy0 is a 16x16 sprite, two bytes per row
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 0..16, 0..16));
+        assert_eq!(c8.gpregs[GPReg::VF], 0);
+
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 0..16, 4..20));
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 4..20, 4..20));
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 0..16, 4..20));
+
+        //Drawing over the scrolled block erases the overlap and reports the collision
+        c8.step(None).unwrap();
+        let hires = c8.clone();
+        assert!(lit_where(&c8.display, |x, y| x < 16 && (y < 4 || (16..20).contains(&y))));
+        assert_eq!(c8.gpregs[GPReg::VF], 1);
+
+        //SUPER-CHIP 1.1 keeps the picture, at half the resolution, and scrolls half as far
+        c8.step(None).unwrap();
+        assert!(!c8.display.is_hires());
+        assert!(lit_where(&c8.display, |x, y| x < 8 && (y < 2 || (8..10).contains(&y))));
+        c8.step(None).unwrap();
+        assert!(lit_where(&c8.display, |x, y| (2..10).contains(&x) && (y < 2 || (8..10).contains(&y))));
+
+        assert_eq!(c8.step(None), Ok(Instr::EXIT));
+        assert!(c8.is_halted());
+        assert_eq!(c8.run_frame(None), Ok(None));
+        assert_eq!(c8.pc, 0x212);
+
+        //XO-CHIP clears the display instead, and scrolls by whole low resolution pixels
+        let mut c8 = hires;
+        c8.set_quirks(Quirks { lores_scaled: false, ..c8.quirks() });
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 0..0, 0..0));
+        c8.display.draw(1, 0, 0, 8, &[0x80], false);
+        c8.step(None).unwrap();
+        assert!(lit_rect(&c8.display, 4..5, 0..1));
+    }
+
+    #[test]
+    fn schip_registers() {
+        //LD V0, 0x09; LD HF, V0; LD V1, 0x02; LD V2, 0x03; LD R, V2; LD V0, 0x00; LD V2, 0x00; LD V1, R
+        let rom = [0x60, 0x09, 0xF0, 0x30, 0x61, 0x02, 0x62, 0x03, 0xF2, 0x75, 0x60, 0x00, 0x62, 0x00, 0xF1, 0x85];
+        let mut c8 = Chip8::load_rom(Platform::SChip11, &rom).unwrap();
+
+        c8.step(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!(c8.i_reg, BIG_FONT_ADDR + 90);
+        assert_eq!(c8.ram[c8.i_reg as usize..][..10], BIG_FONT[9]);
+
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(c8.rpl[..4], [9, 2, 3, 0]);
+
+        //Only V0 and V1 come back, V2 keeps its new value
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(c8.gpregs[..3], [9, 2, 0]);
+        assert_eq!(c8.rpl[..4], [9, 2, 3, 0]);
+    }
+
+    #[test]
+    fn xochip_memory() {
+        //LD V0, 0x01; LD V1, 0x02; LD V2, 0x03; LD I, 0x300; SAVE V0 - V2; LD I, LONG 0x310; SAVE V2 - V0
+        //LOAD V3 - V5; LD I, 0x300; LOAD V8 - V6; SE V0, 0x01; LD I, LONG 0x1234; LD VA, 0xAA
+        let rom = [
+            0x60, 0x01, 0x61, 0x02, 0x62, 0x03, 0xA3, 0x00, 0x50, 0x22, 0xF0, 0x00, 0x03, 0x10, 0x52, 0x02,
+            0x53, 0x53, 0xA3, 0x00, 0x58, 0x63, 0x30, 0x01, 0xF0, 0x00, 0x12, 0x34, 0x6A, 0xAA,
+        ];
+        let mut c8 = Chip8::load_rom(Platform::XoChip, &rom).unwrap();
+
+        for _ in 0..5 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(c8.ram[0x300..0x303], [1, 2, 3]);
+        assert_eq!(c8.i_reg, 0x300);
+
+        assert_eq!(c8.step(None), Ok(Instr::LDIL(0x310)));
+        assert_eq!(c8.pc, 0x20E);
+
+        //A reversed range saves and loads the registers in reverse order
+        c8.step(None).unwrap();
+        assert_eq!(c8.ram[0x310..0x313], [3, 2, 1]);
+        c8.step(None).unwrap();
+        assert_eq!(c8.gpregs[3..6], [3, 2, 1]);
+        c8.step(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!(c8.gpregs[6..9], [3, 2, 1]);
+        assert_eq!(c8.i_reg, 0x300);
+
+        //A skip steps over all 4 bytes of F000 nnnn
+        c8.step(None).unwrap();
+        assert_eq!(c8.pc, 0x21C);
+        c8.step(None).unwrap();
+        assert_eq!((c8.gpregs[GPReg::VA], c8.i_reg), (0xAA, 0x300));
+    }
+
+    #[test]
+    fn xochip_planes_and_audio() {
+        //PLANE 3; LD I, 0x300; DRW V0, V0, 1; AUDIO; LD V0, 0x70; PITCH V0
+        let rom = [0xF3, 0x01, 0xA3, 0x00, 0xD0, 0x01, 0xF0, 0x02, 0x60, 0x70, 0xF0, 0x3A];
+        let pattern = [0x80, 0x40, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xFF];
+        let mut c8 = Chip8::load_rom(Platform::XoChip, &rom).unwrap();
+        c8.ram[0x300..0x310].copy_from_slice(&pattern);
+
+        c8.step(None).unwrap();
+        assert_eq!(c8.display.plane_mask(), 0b11);
+
+        //Each selected plane draws its own copy of the sprite, in plane order
+        c8.step(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!((c8.display.pixel(0, 0), c8.display.pixel(1, 0), c8.display.pixel(0, 1)), (0b01, 0b10, 0));
+        assert_eq!(c8.gpregs[GPReg::VF], 0);
+
+        c8.step(None).unwrap();
+        assert_eq!(c8.audio.pattern, pattern);
+        assert_eq!(c8.audio.pitch, 64);
+
+        c8.step(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!(c8.audio.pitch, 0x70);
+    }
+
+    #[test]
+    fn frames() {
+        //ADD V0, 0x01 over and over
+        let rom = [0x70, 0x01].repeat(0x100);
+
+        //Timers tick once per frame, however many instructions run in it
+        for ipf in [1, 7, 50] {
+            let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+            c8.set_instructions_per_frame(ipf);
+            c8.timers.dt = 10;
+            c8.timers.st = 2;
+            for _ in 0..4 {
+                assert_eq!(c8.run_frame(None), Ok(Some(Instr::ADDL(GPReg::V0, 1))));
+            }
+            assert_eq!((c8.timers.delay(), c8.timers.sound()), (6, 0));
+            assert_eq!(c8.gpregs[GPReg::V0] as usize, 4 * ipf);
+            assert_eq!(c8.pc as usize, 0x200 + 8 * ipf);
+        }
+
+        //A hook that stops the frame leaves the rest of it, and the tick, to the next call
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_instructions_per_frame(7);
+        c8.timers.dt = 10;
+        let mut ran = 0;
+        let stopped = c8.run_frame_with(None, |c8| {
+            ran += 1;
+            let instr = c8.step(None)?;
+            Ok(if ran == 3 { FrameStep::Stop(Some(instr), "third") } else { FrameStep::Ran(instr) })
+        });
+        assert_eq!(stopped, Ok(Some("third")));
+        assert_eq!((c8.gpregs[GPReg::V0], c8.timers.delay()), (3, 10));
+        assert_eq!(c8.run_frame(None), Ok(Some(Instr::ADDL(GPReg::V0, 1))));
+        assert_eq!((c8.gpregs[GPReg::V0], c8.timers.delay()), (7, 9));
+
+        //LD V0, 0x01; LD V1, K; JP 0x204
+        let rom = [0x60, 0x01, 0xF1, 0x0A, 0x12, 0x04];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.timers.dt = 10;
+
+        //A frame stops at a key wait, and still ticks the timers
+        assert_eq!(c8.run_frame(None), Ok(Some(Instr::LDKB(GPReg::V1))));
+        assert_eq!((c8.pc, c8.timers.delay()), (0x202, 9));
+        assert_eq!(c8.run_frame(None), Ok(Some(Instr::LDKB(GPReg::V1))));
+        assert_eq!((c8.pc, c8.timers.delay()), (0x202, 8));
+
+        //JP to itself halts, and a halted machine runs nothing
+        assert!(matches!(c8.run_frame(Some(Key::K7)), Ok(Some(Instr::JP(_)))));
+        assert_eq!((c8.gpregs[GPReg::V1], c8.pc, c8.timers.delay()), (7, 0x204, 7));
+        assert!(c8.is_halted());
+        assert_eq!(c8.run_frame(None), Ok(None));
+        assert_eq!(c8.timers.delay(), 6);
+    }
+
+    #[test]
+    fn seeded() {
+        //RND V0, 0xFF; RND V1, 0xFF; RND V2, 0x0F; RND V3, 0xFF
+        let rom = [0xC0, 0xFF, 0xC1, 0xFF, 0xC2, 0x0F, 0xC3, 0xFF];
+        let run = |seed| {
+            let mut c8 = Chip8::load_rom_seeded(Platform::Modern, &rom, seed).unwrap();
+            for _ in 0..4 {
+                c8.step(None).unwrap();
+            }
+            c8.gpregs
+        };
+
+        assert_eq!(run(0xC8), run(0xC8));
+        assert_ne!(run(0xC8), run(0xC9));
+        assert!(run(0xC8)[2] <= 0x0F);
+    }
+
+    #[test]
+    fn stack() {
+        let call = |addr: u16| [0x20 | (addr >> 8) as u8, addr as u8];
+
+        //CALL 0x300; LD V0, 0x01, with subroutines at 0x300, 0x304 and so on that each
+        //call the next and return, up to a full stack
+        let mut c8 = Chip8::load_rom(Platform::Modern, &[0x23, 0x00, 0x60, 0x01]).unwrap();
+        for depth in 0..STACK_LIMIT - 1 {
+            let addr = 0x300 + 4 * depth;
+            c8.ram[addr..addr + 2].copy_from_slice(&call(addr as u16 + 4));
+            c8.ram[addr + 2..addr + 4].copy_from_slice(&[0x00, 0xEE]);
+        }
+        let last = 0x300 + 4 * (STACK_LIMIT - 1);
+        c8.ram[last..last + 2].copy_from_slice(&[0x00, 0xEE]);
+
+        for _ in 0..STACK_LIMIT {
+            c8.step(None).unwrap();
+        }
+        assert_eq!((c8.sp, c8.pc as usize), (STACK_LIMIT, last));
+        assert_eq!(c8.stack[0], 0x202);
+        for _ in 0..STACK_LIMIT {
+            assert_eq!(c8.step(None), Ok(Instr::RET));
+        }
+        assert_eq!((c8.sp, c8.pc, c8.stack), (0, 0x202, [0; STACK_LIMIT]));
+        c8.step(None).unwrap();
+        assert_eq!(c8.gpregs[GPReg::V0], 1);
+
+        //A 17th call overflows, leaving the stack as it was
+        let mut overflow = Chip8::load_rom(Platform::Modern, &[0x23, 0x00]).unwrap();
+        overflow.ram[0x300..].copy_from_slice(&c8.ram[0x300..]);
+        overflow.ram[last..last + 2].copy_from_slice(&call(last as u16 + 4));
+        for _ in 0..STACK_LIMIT {
+            overflow.step(None).unwrap();
+        }
+        let opcode = 0x2000 | (last as u16 + 4);
+        let stack = overflow.stack;
+        assert_eq!(overflow.step(None), Err(Error::StackOverflow { pc: last as u16, opcode }));
+        assert_eq!((overflow.sp, overflow.stack, overflow.pc as usize), (STACK_LIMIT, stack, last));
+
+        //RET, which fails again when stepped again
+        let mut underflow = Chip8::load_rom(Platform::Modern, &[0x00, 0xEE]).unwrap();
+        for _ in 0..2 {
+            assert_eq!(underflow.step(None), Err(Error::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+            assert_eq!((underflow.sp, underflow.pc), (0, 0x200));
+        }
+    }
+
+    #[test]
+    fn invalid_key() {
+        //LD V0, 0x10; SKP V0
+        let mut c8 = Chip8::load_rom(Platform::Modern, &[0x60, 0x10, 0xE0, 0x9E]).unwrap();
+        c8.step(None).unwrap();
+        let error = Error::InvalidKey { pc: 0x202, opcode: 0xE09E, reg: GPReg::V0, value: 0x10 };
+        assert_eq!(c8.step(None), Err(error));
+        assert_eq!(c8.pc, 0x202);
+    }
+
+    //A Modern machine running `rom` with only the quirks `set` turns on
+    fn with_quirks(rom: &[u8], set: impl Fn(&mut Quirks)) -> Chip8 {
+        let mut quirks = Quirks::default();
+        set(&mut quirks);
+        let mut c8 = Chip8::load_rom(Platform::Modern, rom).unwrap();
+        c8.set_quirks(quirks);
+        c8
+    }
+
+    #[test]
+    fn quirk_display_wait() {
+        //DRW V0, V0, 1; ADD V1, 0x01; DRW V0, V0, 1; ADD V1, 0x01
+        let rom = [0xD0, 0x01, 0x71, 0x01, 0xD0, 0x01, 0x71, 0x01];
+
+        let mut c8 = with_quirks(&rom, |_| {});
+        c8.run_frame(None).unwrap();
+        assert!(!c8.display.pixel_on(0, 0));
+        assert_eq!(c8.gpregs[GPReg::V1], 2);
+
+        //The frame ends right after each DRW
+        let mut c8 = with_quirks(&rom, |q| q.display_wait = true);
+        assert!(matches!(c8.run_frame(None), Ok(Some(Instr::DRW(..)))));
+        assert!(c8.display.pixel_on(0, 0));
+        assert_eq!((c8.pc, c8.gpregs[GPReg::V1]), (0x202, 0));
+        c8.run_frame(None).unwrap();
+        assert!(!c8.display.pixel_on(0, 0));
+        assert_eq!((c8.pc, c8.gpregs[GPReg::V1]), (0x206, 1));
+        c8.run_frame(None).unwrap();
+        assert_eq!(c8.gpregs[GPReg::V1], 2);
+
+        //Stepped on their own, a second DRW in the same frame spins until the timers tick
+        let mut c8 = with_quirks(&rom, |q| q.display_wait = true);
+        for _ in 0..4 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!((c8.pc, c8.gpregs[GPReg::V1]), (0x204, 1));
+        c8.tick_timers();
+        c8.step(None).unwrap();
+        assert_eq!(c8.pc, 0x206);
+        assert!(!c8.display.pixel_on(0, 0));
+    }
+
+    #[test]
+    fn quirk_clipping() {
+        //LD V0, 0x3E; LD I, 0x206; DRW V0, V1, 2; 0xFF 0xFF
+        let rom = [0x60, 0x3E, 0xA2, 0x06, 0xD0, 0x12, 0xFF, 0xFF];
+        let lit = |c8: &Chip8| (0..VRAM_WIDTH).filter(|&x| c8.display.pixel_on(x, 1)).collect::<Vec<_>>();
+
+        let mut c8 = with_quirks(&rom, |_| {});
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(lit(&c8), [0, 1, 2, 3, 4, 5, 62, 63]);
+
+        let mut c8 = with_quirks(&rom, |q| q.clipping = true);
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(lit(&c8), [62, 63]);
+    }
+
+    #[test]
+    fn quirk_jumping() {
+        //LD V0, 0x01; LD V3, 0x04; JP V0, 0x300
+        let rom = [0x60, 0x01, 0x63, 0x04, 0xB3, 0x00];
+
+        let mut c8 = with_quirks(&rom, |_| {});
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(c8.pc, 0x301);
+
+        //Bxnn adds Vx
+        let mut c8 = with_quirks(&rom, |q| q.jumping = true);
+        for _ in 0..3 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!(c8.pc, 0x304);
+    }
+
+    #[test]
+    fn quirk_i_overflow() {
+        //LD VF, 0x55; LD V0, 0x02; LD I, 0xFFF; ADD I, V0; ADD I, V0
+        let rom = [0x6F, 0x55, 0x60, 0x02, 0xAF, 0xFF, 0xF0, 0x1E, 0xF0, 0x1E];
+
+        let mut c8 = with_quirks(&rom, |_| {});
+        for _ in 0..4 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!((c8.i_reg, c8.gpregs[GPReg::VF]), (0x001, 0x55));
+
+        //VF is set on overflow and cleared otherwise
+        let mut c8 = with_quirks(&rom, |q| q.i_overflow = true);
+        for _ in 0..4 {
+            c8.step(None).unwrap();
+        }
+        assert_eq!((c8.i_reg, c8.gpregs[GPReg::VF]), (0x001, 1));
+        c8.step(None).unwrap();
+        assert_eq!((c8.i_reg, c8.gpregs[GPReg::VF]), (0x003, 0));
+    }
 }
\ No newline at end of file
@@ -0,0 +1,23 @@
+/// XO-CHIP audio state. The pattern buffer is a 128-sample, 1-bit waveform
+/// that plays while the sound timer is nonzero.
+#[derive(Debug, Clone, Copy)]
+pub struct Audio {
+    pub pattern: [u8; 0x10],
+    pub pitch: u8,
+}
+
+impl Audio {
+    /// Samples per second the pattern buffer should be played back at
+    pub fn playback_rate(&self) -> f64 {
+        4000.0 * 2f64.powf((self.pitch as f64 - 64.0) / 48.0)
+    }
+}
+
+impl Default for Audio {
+    fn default() -> Self {
+        Audio {
+            pattern: [0x0; 0x10],
+            pitch: 64,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,348 @@
+use super::{HIRES_HEIGHT, HIRES_WH, HIRES_WIDTH, VRAM_HEIGHT, VRAM_WIDTH};
+
+//The pixels and mode an instruction overwrote, see `Display::changes_since`
+#[derive(Debug, Clone)]
+pub(crate) struct DisplayUndo {
+    pixels: Vec<(u16, u8)>,
+    hires: bool,
+    plane_mask: u8,
+}
+
+/// The framebuffer of a `Chip8`.
+///
+/// Pixels are stored as a bitmask of the planes they are lit on, bit 0 being
+/// the first plane. Frontends should check `take_changed` once per frame and
+/// only redraw the rows reported by `dirty_rows`.
+#[derive(Debug, Clone)]
+pub struct Display {
+    pixels: [u8; HIRES_WH],
+    hires: bool,
+    planes: usize,
+    plane_mask: u8,
+    dirty: u64,
+    changed: bool,
+}
+
+impl Display {
+    pub fn new(planes: usize) -> Self {
+        assert!((1..=8).contains(&planes), "A display must have between 1 and 8 planes!");
+
+        Display {
+            pixels: [0x0; HIRES_WH],
+            hires: false,
+            planes,
+            plane_mask: 0x1,
+            dirty: u64::MAX,
+            changed: true,
+        }
+    }
+
+    /// Width of the display in the current resolution
+    pub fn width(&self) -> usize {
+        if self.hires { HIRES_WIDTH } else { VRAM_WIDTH }
+    }
+
+    /// Height of the display in the current resolution
+    pub fn height(&self) -> usize {
+        if self.hires { HIRES_HEIGHT } else { VRAM_HEIGHT }
+    }
+
+    pub fn planes(&self) -> usize {
+        self.planes
+    }
+
+    pub fn is_hires(&self) -> bool {
+        self.hires
+    }
+
+    /// Switch resolution. XO-CHIP clears every plane. With `keep`, the picture is kept
+    /// like on SUPER-CHIP 1.1, which draws low resolution pixels as 2x2 blocks of the
+    /// high resolution framebuffer.
+    pub fn set_hires(&mut self, hires: bool, keep: bool) {
+        if !keep {
+            self.pixels.fill(0);
+        } else if hires != self.hires {
+            let old = self.pixels;
+            self.pixels = [0x0; HIRES_WH];
+            for y in 0..HIRES_HEIGHT {
+                for x in 0..HIRES_WIDTH {
+                    //Low resolution pixels are 2x2 blocks, so going back samples every other one
+                    if hires {
+                        self.pixels[y * HIRES_WIDTH + x] = old[y / 2 * VRAM_WIDTH + x / 2];
+                    } else if x < VRAM_WIDTH && y < VRAM_HEIGHT {
+                        self.pixels[y * VRAM_WIDTH + x] = old[2 * y * HIRES_WIDTH + 2 * x];
+                    }
+                }
+            }
+        }
+        self.hires = hires;
+        self.mark_all_dirty();
+    }
+
+    /// Bitmask of the planes selected for drawing
+    pub fn plane_mask(&self) -> u8 {
+        self.plane_mask
+    }
+
+    pub fn select_planes(&mut self, mask: u8) {
+        self.plane_mask = mask & self.all_planes();
+    }
+
+    /// Iterate over the bit of each selected plane, in plane order
+    pub fn selected_planes(&self) -> impl Iterator<Item = u8> + '_ {
+        (0..self.planes)
+            .map(|plane| 1 << plane)
+            .filter(|bit| self.plane_mask & bit != 0)
+    }
+
+    /// The planes lit at (x, y)
+    pub fn pixel(&self, x: usize, y: usize) -> u8 {
+        self.pixels[y * self.width() + x]
+    }
+
+    pub fn pixel_on(&self, x: usize, y: usize) -> bool {
+        self.pixel(x, y) != 0
+    }
+
+    /// One row of pixels in the current resolution
+    pub fn row(&self, y: usize) -> &[u8] {
+        let width = self.width();
+        &self.pixels[y * width..(y + 1) * width]
+    }
+
+    /// Returns whether anything was drawn since the last call, and resets the flag
+    pub fn take_changed(&mut self) -> bool {
+        std::mem::take(&mut self.changed)
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.changed
+    }
+
+    /// Rows modified since the last `clear_dirty`
+    pub fn dirty_rows(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.height()).filter(|y| self.dirty & (1 << y) != 0)
+    }
+
+    pub fn clear_dirty(&mut self) {
+        self.dirty = 0;
+    }
+
+    /// Mark every row as needing a redraw, e.g. after the frontend lost its buffer
+    pub fn mark_all_dirty(&mut self) {
+        self.dirty = u64::MAX;
+        self.changed = true;
+    }
+
+    pub(crate) fn raw_pixels(&self) -> &[u8; HIRES_WH] {
+        &self.pixels
+    }
+
+    //Rebuild a display from savestate data. Everything is marked dirty so frontends repaint.
+    pub(crate) fn from_raw(planes: usize, hires: bool, plane_mask: u8, pixels: [u8; HIRES_WH]) -> Self {
+        let mut display = Display::new(planes);
+        display.hires = hires;
+        display.select_planes(plane_mask);
+        display.pixels = pixels;
+        display
+    }
+
+    //What it takes to turn this display back into `before`
+    pub(crate) fn changes_since(&self, before: &Display) -> DisplayUndo {
+        let pixels = self.pixels.iter()
+            .zip(&before.pixels)
+            .enumerate()
+            .filter(|(_, (now, old))| now != old)
+            .map(|(idx, (_, &old))| (idx as u16, old))
+            .collect();
+        DisplayUndo { pixels, hires: before.hires, plane_mask: before.plane_mask }
+    }
+
+    pub(crate) fn undo(&mut self, undo: &DisplayUndo) {
+        for &(idx, old) in &undo.pixels {
+            self.pixels[idx as usize] = old;
+        }
+        self.hires = undo.hires;
+        self.plane_mask = undo.plane_mask;
+        self.mark_all_dirty();
+    }
+
+    fn mark_dirty(&mut self, y: usize) {
+        self.dirty |= 1 << y;
+        self.changed = true;
+    }
+
+    fn all_planes(&self) -> u8 {
+        ((1u16 << self.planes) - 1) as u8
+    }
+
+    /// Clear the selected planes
+    pub fn clear(&mut self) {
+        let mask = self.plane_mask;
+        self.pixels.iter_mut().for_each(|pix| *pix &= !mask);
+        self.mark_all_dirty();
+    }
+
+    /// Shift the selected planes by (dx, dy), clearing whatever is scrolled in
+    pub fn scroll(&mut self, dx: isize, dy: isize) {
+        let (w, h) = (self.width() as isize, self.height() as isize);
+        let mask = self.plane_mask;
+        let old = self.pixels;
+
+        for y in 0..h {
+            for x in 0..w {
+                let (src_x, src_y) = (x - dx, y - dy);
+                let src = if (0..w).contains(&src_x) && (0..h).contains(&src_y) {
+                    old[(src_y * w + src_x) as usize]
+                } else {
+                    0
+                };
+
+                let pix = &mut self.pixels[(y * w + x) as usize];
+                *pix = (*pix & !mask) | (src & mask);
+            }
+        }
+
+        self.mark_all_dirty();
+    }
+
+    /// XOR a sprite `spr_width` pixels wide onto `plane` with its top left corner at (x, y).
+    /// `sprite` holds the rows top to bottom, `spr_width / 8` bytes each. Pixels that
+    /// fall off the display are clipped, or wrap around to the other side if `wrap` is set.
+    /// Returns whether any lit pixel was erased.
+    pub fn draw(&mut self, plane: u8, x: usize, y: usize, spr_width: usize, sprite: &[u8], wrap: bool) -> bool {
+        let (width, height) = (self.width(), self.height());
+        let bytes_per_row = spr_width / 8;
+        let mut collided = false;
+
+        for (row, bytes) in sprite.chunks(bytes_per_row).enumerate() {
+            let y_coord = if wrap { (y + row) % height } else { y + row };
+            if y_coord >= height {
+                break;
+            }
+
+            for col in 0..spr_width {
+                let mask = 0b10000000 >> (col % 8);
+                let x_coord = if wrap { (x + col) % width } else { x + col };
+
+                if bytes[col / 8] & mask != mask || x_coord >= width {
+                    continue;
+                }
+
+                let pix = &mut self.pixels[y_coord * width + x_coord];
+                collided |= *pix & plane != 0;
+                *pix ^= plane;
+            }
+
+            self.mark_dirty(y_coord);
+        }
+
+        collided
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    //A display with nothing changed or dirty yet
+    fn clean(planes: usize) -> Display {
+        let mut display = Display::new(planes);
+        display.clear_dirty();
+        display.take_changed();
+        display
+    }
+
+    fn lit(display: &Display) -> Vec<(usize, usize)> {
+        (0..display.height())
+            .flat_map(|y| (0..display.width()).map(move |x| (x, y)))
+            .filter(|&(x, y)| display.pixel_on(x, y))
+            .collect()
+    }
+
+    #[test]
+    fn dirty_tracking() {
+        let mut display = Display::new(1);
+        assert!(display.take_changed());
+        assert_eq!(display.dirty_rows().count(), VRAM_HEIGHT);
+
+        let mut display = clean(1);
+        assert!(!display.is_changed());
+        assert!(!display.draw(1, 4, 10, 8, &[0xFF, 0x00, 0x81], false));
+        assert_eq!(display.dirty_rows().collect::<Vec<_>>(), [10, 11, 12]);
+        assert!(display.take_changed());
+        assert!(!display.take_changed());
+
+        //Redrawing erases the sprite and reports the collision
+        display.clear_dirty();
+        assert!(display.draw(1, 4, 10, 8, &[0xFF, 0x00, 0x81], false));
+        assert_eq!(display.dirty_rows().collect::<Vec<_>>(), [10, 11, 12]);
+        assert!(lit(&display).is_empty());
+
+        for update in [Display::clear as fn(&mut Display), |d| d.scroll(0, 1), |d| d.set_hires(true, false)] {
+            display.clear_dirty();
+            display.take_changed();
+            update(&mut display);
+            assert!(display.take_changed());
+            assert_eq!(display.dirty_rows().count(), display.height());
+        }
+        assert_eq!(display.dirty_rows().count(), HIRES_HEIGHT);
+    }
+
+    #[test]
+    fn wrap_and_clip() {
+        let sprite = [0xC0, 0xC0];
+
+        let mut display = clean(1);
+        display.draw(1, VRAM_WIDTH - 1, VRAM_HEIGHT - 1, 8, &sprite, true);
+        assert_eq!(lit(&display), [(0, 0), (63, 0), (0, 31), (63, 31)]);
+        assert_eq!(display.dirty_rows().collect::<Vec<_>>(), [0, 31]);
+
+        let mut display = clean(1);
+        display.draw(1, VRAM_WIDTH - 1, VRAM_HEIGHT - 1, 8, &sprite, false);
+        assert_eq!(lit(&display), [(63, 31)]);
+        assert_eq!(display.dirty_rows().collect::<Vec<_>>(), [31]);
+
+        //16 pixel wide sprites wrap too, in high resolution
+        let mut display = clean(1);
+        display.set_hires(true, false);
+        display.draw(1, HIRES_WIDTH - 8, 0, 16, &[0x80, 0x01], true);
+        assert_eq!(lit(&display), [(7, 0), (120, 0)]);
+    }
+
+    #[test]
+    fn planes() {
+        let mut display = clean(2);
+        display.select_planes(0xFF);
+        assert_eq!(display.plane_mask(), 0b11);
+        assert_eq!(display.selected_planes().collect::<Vec<_>>(), [1, 2]);
+
+        display.draw(1, 0, 0, 8, &[0x80], false);
+        display.draw(2, 0, 0, 8, &[0xC0], false);
+        assert_eq!((display.pixel(0, 0), display.pixel(1, 0)), (0b11, 0b10));
+
+        //Clearing and scrolling only touch the selected planes
+        display.select_planes(0b01);
+        display.scroll(0, 1);
+        assert_eq!((display.pixel(0, 0), display.pixel(1, 0), display.pixel(0, 1)), (0b10, 0b10, 0b01));
+        display.clear();
+        assert_eq!((display.pixel(0, 0), display.pixel(1, 0), display.pixel(0, 1)), (0b10, 0b10, 0));
+    }
+
+    #[test]
+    fn resolution_switch() {
+        let mut display = clean(1);
+        display.draw(1, 1, 2, 8, &[0x80], false);
+        display.set_hires(true, false);
+        assert!(lit(&display).is_empty());
+
+        //Kept pictures scale up to 2x2 blocks and back down
+        let mut display = clean(1);
+        display.draw(1, 1, 2, 8, &[0x80], false);
+        display.set_hires(true, true);
+        assert_eq!(lit(&display), [(2, 4), (3, 4), (2, 5), (3, 5)]);
+        display.draw(1, 127, 63, 8, &[0x80], false);
+        display.set_hires(false, true);
+        assert_eq!(lit(&display), [(1, 2)]);
+    }
+}
\ No newline at end of file
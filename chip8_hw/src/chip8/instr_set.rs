@@ -0,0 +1,42 @@
+use chip8_decode::instructions::Instr;
+
+use super::{PLANES, RAM_SIZE, XO_RAM_SIZE};
+
+/// The instruction set extensions a `Chip8` will execute.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum InstrSet {
+    /// The original CHIP-8 instructions only
+    #[default]
+    Chip8,
+    /// CHIP-8 plus the SUPER-CHIP 1.1 extensions
+    SChip,
+    /// SUPER-CHIP plus the XO-CHIP extensions: 64K of RAM, bitplanes and audio
+    XoChip,
+}
+
+impl InstrSet {
+    pub fn has_schip(&self) -> bool {
+        matches!(self, InstrSet::SChip | InstrSet::XoChip)
+    }
+
+    pub fn has_xochip(&self) -> bool {
+        matches!(self, InstrSet::XoChip)
+    }
+
+    pub fn ram_size(&self) -> usize {
+        if self.has_xochip() { XO_RAM_SIZE } else { RAM_SIZE }
+    }
+
+    pub fn planes(&self) -> usize {
+        if self.has_xochip() { PLANES } else { 1 }
+    }
+
+    pub fn supports(&self, instr: &Instr) -> bool {
+        use chip8_decode::instructions::Instr::*;
+        match instr {
+            SCD(_) | SCR | SCL | EXIT | LOW | HIGH | LDHSPR(_) | PUSHRPL(_) | POPRPL(_) => self.has_schip(),
+            SCU(_) | PUSHRANGE(..) | POPRANGE(..) | LDIL(_) | PLANE(_) | AUDIO | PITCH(_) => self.has_xochip(),
+            _ => true,
+        }
+    }
+}
\ No newline at end of file
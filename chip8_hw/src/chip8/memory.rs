@@ -0,0 +1,23 @@
+/// What happens when an instruction reaches past the end of RAM through I.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryPolicy {
+    /// The address wraps around to the start of RAM
+    #[default]
+    Wrap,
+    /// The address is clamped to the last byte of RAM
+    Clamp,
+    /// The instruction fails with `Error::MemoryFault` and has no effect
+    Fault,
+}
+
+impl MemoryPolicy {
+    /// Map `addr` into a RAM of `len` bytes. None if the access should fault.
+    pub fn resolve(&self, addr: usize, len: usize) -> Option<usize> {
+        match self {
+            _ if addr < len => Some(addr),
+            MemoryPolicy::Wrap => Some(addr % len),
+            MemoryPolicy::Clamp => Some(len - 1),
+            MemoryPolicy::Fault => None,
+        }
+    }
+}
\ No newline at end of file
@@ -0,0 +1,184 @@
+use std::str::FromStr;
+
+use chip8_decode::options::DecodeOptions;
+
+use super::{instr_set::InstrSet, quirks::Quirks, HIRES_HEIGHT, HIRES_WIDTH, VRAM_HEIGHT, VRAM_WIDTH};
+
+/// A well-known CHIP-8 target, bundling everything that differs between them.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Platform {
+    /// The original interpreter on the RCA COSMAC VIP
+    CosmacVip,
+    /// CHIP-48 on the HP 48 calculators
+    Chip48,
+    /// SUPER-CHIP 1.1 on the HP 48
+    SChip11,
+    /// Octo's XO-CHIP
+    XoChip,
+    /// CHIP-8 as most modern interpreters run it
+    #[default]
+    Modern,
+}
+
+impl Platform {
+    pub const ALL: [Platform; 5] = [Platform::CosmacVip, Platform::Chip48, Platform::SChip11, Platform::XoChip, Platform::Modern];
+
+    /// The name used to select this platform on the command line
+    pub fn name(&self) -> &'static str {
+        match self {
+            Platform::CosmacVip => "vip",
+            Platform::Chip48 => "chip48",
+            Platform::SChip11 => "schip",
+            Platform::XoChip => "xochip",
+            Platform::Modern => "modern",
+        }
+    }
+
+    pub fn instr_set(&self) -> InstrSet {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 | Platform::Modern => InstrSet::Chip8,
+            Platform::SChip11 => InstrSet::SChip,
+            Platform::XoChip => InstrSet::XoChip,
+        }
+    }
+
+    pub fn ram_size(&self) -> usize {
+        self.instr_set().ram_size()
+    }
+
+    /// The largest resolution the platform can switch to
+    pub fn display_size(&self) -> (usize, usize) {
+        if self.instr_set().has_schip() {
+            (HIRES_WIDTH, HIRES_HEIGHT)
+        } else {
+            (VRAM_WIDTH, VRAM_HEIGHT)
+        }
+    }
+
+    /// Default clock speed, in instructions executed per 60 Hz frame
+    pub fn instructions_per_frame(&self) -> usize {
+        match self {
+            Platform::CosmacVip => 10,
+            Platform::Chip48 | Platform::Modern => 15,
+            Platform::SChip11 => 30,
+            Platform::XoChip => 200,
+        }
+    }
+
+    /// How undefined opcodes are decoded. The original interpreters never validated
+    /// opcodes and kept running whatever they dispatched to, so they skip them here
+    /// instead of halting.
+    pub fn decode_options(&self) -> DecodeOptions {
+        match self {
+            Platform::CosmacVip | Platform::Chip48 => DecodeOptions::LENIENT,
+            Platform::SChip11 | Platform::XoChip | Platform::Modern => DecodeOptions::STRICT,
+        }
+    }
+
+    pub fn quirks(&self) -> Quirks {
+        match self {
+            Platform::CosmacVip => Quirks {
+                vf_reset: true,
+                memory: true,
+                shifting: false,
+                display_wait: true,
+                clipping: true,
+                jumping: false,
+                i_overflow: false,
+                lores_scaled: false,
+            },
+            Platform::Chip48 | Platform::SChip11 => Quirks {
+                vf_reset: false,
+                memory: false,
+                shifting: true,
+                display_wait: false,
+                clipping: true,
+                jumping: true,
+                i_overflow: false,
+                lores_scaled: *self == Platform::SChip11,
+            },
+            Platform::XoChip => Quirks {
+                vf_reset: false,
+                memory: true,
+                shifting: false,
+                display_wait: false,
+                clipping: false,
+                jumping: false,
+                i_overflow: false,
+                lores_scaled: false,
+            },
+            Platform::Modern => Quirks {
+                vf_reset: true,
+                memory: true,
+                shifting: false,
+                display_wait: false,
+                clipping: true,
+                jumping: false,
+                i_overflow: false,
+                lores_scaled: false,
+            },
+        }
+    }
+}
+
+impl FromStr for Platform {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Platform::ALL.into_iter()
+            .find(|platform| platform.name().eq_ignore_ascii_case(s))
+            .ok_or_else(|| {
+                let names: Vec<_> = Platform::ALL.iter().map(Platform::name).collect();
+                format!("Unknown platform \"{s}\", expected one of: {}", names.join(", "))
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_decode::options::Undefined;
+
+    use super::*;
+    use crate::chip8::{RAM_SIZE, XO_RAM_SIZE};
+
+    #[test]
+    fn names() {
+        for platform in Platform::ALL {
+            assert_eq!(platform.name().parse(), Ok(platform));
+            assert_eq!(platform.name().to_uppercase().parse(), Ok(platform));
+        }
+        assert!("chip-8".parse::<Platform>().unwrap_err().contains("vip, chip48, schip, xochip, modern"));
+        assert_eq!(Platform::default(), Platform::Modern);
+    }
+
+    #[test]
+    fn profiles() {
+        let vip = Platform::CosmacVip;
+        assert_eq!((vip.instr_set(), vip.ram_size(), vip.display_size()), (InstrSet::Chip8, RAM_SIZE, (64, 32)));
+        assert!(vip.quirks().vf_reset && vip.quirks().display_wait && !vip.quirks().shifting);
+        assert!(matches!(vip.decode_options().undefined, Undefined::Nop));
+
+        let chip48 = Platform::Chip48;
+        assert_eq!((chip48.instr_set(), chip48.ram_size()), (InstrSet::Chip8, RAM_SIZE));
+        assert!(chip48.quirks().shifting && chip48.quirks().jumping && !chip48.quirks().memory);
+        assert!(matches!(chip48.decode_options().undefined, Undefined::Nop));
+
+        let schip = Platform::SChip11;
+        assert_eq!((schip.instr_set(), schip.ram_size(), schip.display_size()), (InstrSet::SChip, RAM_SIZE, (128, 64)));
+        assert_eq!(schip.quirks(), Quirks { lores_scaled: true, ..chip48.quirks() });
+        assert!(matches!(schip.decode_options().undefined, Undefined::Reject));
+
+        let xo = Platform::XoChip;
+        assert_eq!((xo.instr_set(), xo.ram_size(), xo.display_size()), (InstrSet::XoChip, XO_RAM_SIZE, (128, 64)));
+        assert!(!xo.quirks().clipping && xo.quirks().memory && !xo.quirks().vf_reset);
+        assert!(matches!(xo.decode_options().undefined, Undefined::Reject));
+
+        let modern = Platform::Modern;
+        assert_eq!((modern.instr_set(), modern.ram_size(), modern.display_size()), (InstrSet::Chip8, RAM_SIZE, (64, 32)));
+        assert!(modern.quirks().vf_reset && modern.quirks().clipping && !modern.quirks().display_wait);
+        assert!(matches!(modern.decode_options().undefined, Undefined::Reject));
+
+        let ipf: Vec<_> = Platform::ALL.iter().map(Platform::instructions_per_frame).collect();
+        assert_eq!(ipf, [10, 15, 30, 200, 15]);
+    }
+}
\ No newline at end of file
@@ -1,22 +1,22 @@
-#[derive(Debug, Default, Clone, Copy)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Quirks {
     /// VF is reset to 0 for AND, OR, and XOR opcodes
     pub vf_reset: bool,
     /// PUSHREG and POPREG modify the value of I
     pub memory: bool,
+    /// SHRC and SHLC shift Vx in place, ignoring Vy
     pub shifting: bool,
-}
-
-#[allow(private_interfaces)]
-pub static QUIRKS_OLD: Quirks = Quirks {
-    vf_reset: true,
-    memory: false,
-    shifting: false,
-};
-
-#[allow(private_interfaces)]
-pub static QUIRKS_NEW: Quirks = Quirks {
-    vf_reset: true,
-    memory: true,
-    shifting: false,
-};
\ No newline at end of file
+    /// DRW waits for the vertical blank: the frame ends after it, and a second DRW in the
+    /// same frame spins until the next timer tick
+    pub display_wait: bool,
+    /// Sprites are clipped at the edges of the display instead of wrapping around
+    pub clipping: bool,
+    /// JPL jumps to nnn + Vx, where x is the highest nibble of nnn, instead of nnn + V0
+    pub jumping: bool,
+    /// ADDI sets VF to 1 when I overflows past the address space, and 0 otherwise
+    pub i_overflow: bool,
+    /// Low resolution is drawn at 2x2 on the high resolution framebuffer, like SUPER-CHIP 1.1.
+    /// Switching resolution keeps the picture instead of clearing it, and scrolling in low
+    /// resolution moves half as many pixels, rounded down.
+    pub lores_scaled: bool,
+}
\ No newline at end of file
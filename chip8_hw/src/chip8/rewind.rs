@@ -0,0 +1,408 @@
+//Rewind history: periodic snapshots plus an undo record for every instruction and timer tick.
+//
+//History is kept per frame, a frame being everything between two timer ticks. A frame
+//records the registers as they were before the tick that started it and, for each step,
+//the registers as they were before it ran, the old value of every byte it wrote and, if
+//it touched the display, the pixels it changed. Every `SNAPSHOT_FRAMES` frames also keep
+//a snapshot of the whole machine as the frame's first instruction was about to run: RAM,
+//display, registers and keys. The start of a frame is that point, after the host updated
+//the keys for it.
+//
+//Going back to a point restores the nearest snapshot after it, then undoes the steps and
+//ticks in between, so rewinding costs at most `SNAPSHOT_FRAMES` frames of undoing however
+//long the history is.
+//
+//History holds `capacity` frames besides the current one and only ever drops whole frames,
+//oldest first. A frame that runs `MAX_FRAME_STEPS` instructions without a timer tick, e.g.
+//while single stepping, is split there, and each part counts as a frame of its own.
+//
+//Only changes made by `step` and `tick_timers` are recorded. Poking RAM or registers
+//directly, e.g. from a debugger, is not undone.
+
+use std::collections::VecDeque;
+
+use chip8_decode::instructions::Instr;
+
+use super::display::DisplayUndo;
+use super::{Audio, Chip8, Display, Keyboard, Rng, Timers, STACK_LIMIT};
+
+//Instructions a frame holds before it is split. Well above the busiest platform's
+//instructions per frame.
+const MAX_FRAME_STEPS: usize = 1000;
+
+//Frames between snapshots: one second
+const SNAPSHOT_FRAMES: usize = 60;
+
+#[derive(Debug, Clone)]
+pub(super) struct Regs {
+    gpregs: [u8; 0x10],
+    i_reg: u16,
+    pc: u16,
+    sp: usize,
+    stack: [u16; STACK_LIMIT],
+    rpl: [u8; 0x10],
+    keyboard: Keyboard,
+    halted: bool,
+    frame_pos: usize,
+    drawn_this_frame: bool,
+    timers: Timers,
+    audio: Audio,
+    //Only kept by snapshots and RND, the only instruction that advances it
+    rng: Option<Rng>,
+}
+
+#[derive(Debug, Clone)]
+struct Step {
+    regs: Regs,
+    ram: Vec<(u16, u8)>,
+    display: Option<DisplayUndo>,
+}
+
+#[derive(Debug, Clone)]
+struct Snapshot {
+    regs: Regs,
+    ram: Vec<u8>,
+    display: Display,
+}
+
+#[derive(Debug, Clone)]
+struct Frame {
+    //The registers before the tick that started the frame. None for the frame rewinding
+    //was enabled in and for the second part of a split frame.
+    tick: Option<Regs>,
+    snapshot: Option<Box<Snapshot>>,
+    steps: Vec<Step>,
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct Rewind {
+    //Oldest first, the last one is the current frame. Never empty.
+    frames: VecDeque<Frame>,
+    capacity: usize,
+    //Old values of the bytes the current step wrote
+    pub(super) overwritten: Vec<(u16, u8)>,
+}
+
+impl Rewind {
+    fn start_frame(&mut self, tick: Option<Regs>) {
+        self.frames.push_back(Frame { tick, snapshot: None, steps: Vec::new() });
+        while self.frames.len() > self.capacity + 1 {
+            self.frames.pop_front();
+        }
+    }
+
+    //Whether the next step is the first of a frame that should take a snapshot
+    fn snapshot_due(&self) -> bool {
+        self.frames.back().is_some_and(|frame| frame.steps.is_empty())
+            && self.frames.iter().rev().take(SNAPSHOT_FRAMES).all(|frame| frame.snapshot.is_none())
+    }
+}
+
+//The state a step may change, taken before it runs
+pub(super) struct Before {
+    regs: Regs,
+    display: Option<Box<Display>>,
+}
+
+impl Chip8 {
+    /// Start recording enough history to rewind `frames` frames, dropping any
+    /// history recorded so far. Savestates don't keep the history.
+    pub fn enable_rewind(&mut self, frames: usize) {
+        let first = Frame { tick: None, snapshot: None, steps: Vec::new() };
+        self.rewind = Some(Box::new(Rewind {
+            frames: VecDeque::from([first]),
+            capacity: frames,
+            overwritten: Vec::new(),
+        }));
+    }
+
+    pub fn disable_rewind(&mut self) {
+        self.rewind = None;
+    }
+
+    /// How many whole frames `rewind` can currently go back
+    pub fn rewind_frames(&self) -> usize {
+        self.rewind.as_ref().map_or(0, |rewind| rewind.frames.len() - 1)
+    }
+
+    /// Undo the last instruction, along with any timer ticks since. False if there is
+    /// no recorded instruction to undo.
+    pub fn step_back(&mut self) -> bool {
+        self.undo_to(|frames| {
+            let (idx, frame) = frames.iter().enumerate().rev().find(|(_, frame)| !frame.steps.is_empty())?;
+            Some((idx, frame.steps.len() - 1))
+        })
+    }
+
+    /// Go back to the start of the frame `frames` frames before the current one.
+    /// Returns how many frames were actually rewound, which is less if the history runs out.
+    pub fn rewind(&mut self, frames: usize) -> usize {
+        let rewound = frames.min(self.rewind_frames());
+        self.undo_to(|history| Some((history.len() - 1 - rewound, 0)));
+        rewound
+    }
+
+    /// Run backwards until just before the last instruction that wrote to `addr`.
+    /// False, with nothing undone, if no recorded instruction did.
+    pub fn rewind_to_write(&mut self, addr: u16) -> bool {
+        self.undo_to(|frames| {
+            frames.iter().enumerate().rev().find_map(|(idx, frame)| {
+                let step = frame.steps.iter().rposition(|step| step.ram.iter().any(|&(a, _)| a == addr))?;
+                Some((idx, step))
+            })
+        })
+    }
+
+    pub(super) fn before_step(&mut self, instr: &Instr) -> Option<Before> {
+        let rewind = self.rewind.as_mut()?;
+        rewind.overwritten.clear();
+        if rewind.frames.back().is_some_and(|frame| frame.steps.len() == MAX_FRAME_STEPS) {
+            rewind.start_frame(None);
+        }
+        if rewind.snapshot_due() {
+            let snapshot = self.snapshot();
+            let rewind = self.rewind.as_mut().expect("rewinding was just checked for");
+            rewind.frames.back_mut().expect("history is never empty").snapshot = Some(snapshot);
+        }
+
+        let display = instr.effects().display.then(|| Box::new(self.display.clone()));
+        let rng = matches!(instr, Instr::RND(..)).then(|| self.rng.clone());
+        Some(Before { regs: self.regs(rng), display })
+    }
+
+    pub(super) fn after_step(&mut self, before: Before) {
+        let display = before.display.map(|old| self.display.changes_since(&old));
+        if let Some(rewind) = self.rewind.as_mut() {
+            let ram = std::mem::take(&mut rewind.overwritten);
+            let frame = rewind.frames.back_mut().expect("history is never empty");
+            frame.steps.push(Step { regs: before.regs, ram, display });
+        }
+    }
+
+    //The registers before a timer tick
+    pub(super) fn before_tick(&self) -> Option<Regs> {
+        self.rewind.is_some().then(|| self.regs(None))
+    }
+
+    pub(super) fn after_tick(&mut self, before: Option<Regs>) {
+        if let (Some(rewind), Some(regs)) = (self.rewind.as_mut(), before) {
+            rewind.start_frame(Some(regs));
+        }
+    }
+
+    //Go back to just before step `step` of frame `frame`, as picked by `target` from the
+    //history, and drop everything after it. False if there is no such point.
+    fn undo_to(&mut self, target: impl FnOnce(&VecDeque<Frame>) -> Option<(usize, usize)>) -> bool {
+        let Some(mut rewind) = self.rewind.take() else {
+            return false;
+        };
+        let Some((frame, step)) = target(&rewind.frames) else {
+            self.rewind = Some(rewind);
+            return false;
+        };
+
+        //Start from the nearest snapshot at or after the target instead of undoing everything since
+        let first = if step == 0 { frame } else { frame + 1 };
+        if let Some(snap) = (first..rewind.frames.len()).find(|&idx| rewind.frames[idx].snapshot.is_some()) {
+            rewind.frames.truncate(snap + 1);
+            let back = rewind.frames.back_mut().expect("the snapshot's frame was kept");
+            back.steps.clear();
+            self.restore(back.snapshot.as_ref().expect("the frame was picked for its snapshot"));
+        }
+
+        loop {
+            let last = rewind.frames.len() - 1;
+            let back = rewind.frames.back_mut().expect("history is never empty");
+            let keep = if last == frame { step } else { 0 };
+            while back.steps.len() > keep {
+                let undo = back.steps.pop().expect("steps were just counted");
+                self.undo_step(undo);
+            }
+            if last == frame {
+                break;
+            }
+            if let Some(regs) = rewind.frames.pop_back().and_then(|undone| undone.tick) {
+                self.restore_regs(regs);
+            }
+        }
+
+        self.rewind = Some(rewind);
+        true
+    }
+
+    fn snapshot(&self) -> Box<Snapshot> {
+        Box::new(Snapshot {
+            regs: self.regs(Some(self.rng.clone())),
+            ram: self.ram.clone(),
+            display: self.display.clone(),
+        })
+    }
+
+    fn restore(&mut self, snapshot: &Snapshot) {
+        self.ram.copy_from_slice(&snapshot.ram);
+        self.display = snapshot.display.clone();
+        self.display.mark_all_dirty();
+        self.restore_regs(snapshot.regs.clone());
+    }
+
+    fn regs(&self, rng: Option<Rng>) -> Regs {
+        Regs {
+            gpregs: self.gpregs,
+            i_reg: self.i_reg,
+            pc: self.pc,
+            sp: self.sp,
+            stack: self.stack,
+            rpl: self.rpl,
+            keyboard: self.keyboard.clone(),
+            halted: self.halted,
+            frame_pos: self.frame_pos,
+            drawn_this_frame: self.drawn_this_frame,
+            timers: self.timers.clone(),
+            audio: self.audio,
+            rng,
+        }
+    }
+
+    fn undo_step(&mut self, step: Step) {
+        for (addr, old) in step.ram.into_iter().rev() {
+            self.ram[addr as usize] = old;
+        }
+        if let Some(display) = step.display {
+            self.display.undo(&display);
+        }
+        self.restore_regs(step.regs);
+    }
+
+    fn restore_regs(&mut self, regs: Regs) {
+        self.gpregs = regs.gpregs;
+        self.i_reg = regs.i_reg;
+        self.pc = regs.pc;
+        self.sp = regs.sp;
+        self.stack = regs.stack;
+        self.rpl = regs.rpl;
+        self.keyboard = regs.keyboard;
+        self.halted = regs.halted;
+        self.frame_pos = regs.frame_pos;
+        self.drawn_this_frame = regs.drawn_this_frame;
+        self.timers = regs.timers;
+        self.audio = regs.audio;
+        if let Some(rng) = regs.rng {
+            self.rng = rng;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chip8::{keyboard::Key, Platform, Quirks};
+
+    use super::*;
+
+    #[test]
+    fn step_back_and_rewind() {
+        //LD I, 0x300; LD V0, 0x42; LD [I], V0; DRW V0, V0, 1; ADD V0, 0x01; JP 0x204
+        let rom = [0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0xD0, 0x01, 0x70, 0x01, 0x12, 0x04];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_quirks(Quirks { display_wait: false, memory: false, ..c8.quirks() });
+        c8.enable_rewind(4);
+        for _ in 0..10 {
+            c8.run_frame(None).unwrap();
+        }
+        assert_eq!(c8.rewind_frames(), 4);
+
+        let before = c8.clone();
+        c8.step(None).unwrap();
+        assert!(c8.step_back());
+        assert_eq!((c8.pc, c8.gpregs, c8.i_reg), (before.pc, before.gpregs, before.i_reg));
+
+        //Back to the last LD [I], V0, with the byte it overwrote restored
+        assert!(c8.rewind_to_write(0x300));
+        assert_eq!(c8.pc, 0x204);
+        assert_eq!(c8.ram[0x300], c8.gpregs[0].wrapping_sub(1));
+        assert!(!c8.rewind_to_write(0x301));
+
+        //Back to the start of this frame, then two frames and a step forward and back again
+        assert_eq!(c8.rewind(0), 0);
+        let frame = c8.clone();
+        c8.run_frame(None).unwrap();
+        c8.run_frame(None).unwrap();
+        c8.step(None).unwrap();
+        assert_eq!(c8.rewind(2), 2);
+        assert_eq!((c8.gpregs, c8.i_reg), (frame.gpregs, frame.i_reg));
+        assert_eq!(c8.display.raw_pixels(), frame.display.raw_pixels());
+    }
+
+    #[test]
+    fn snapshots() {
+        //LD I, 0x300; ADD V0, 0x01; LD B, V0; DRW V0, V1, 3; LD DT, V0; JP 0x202
+        let rom = [0xA3, 0x00, 0x70, 0x01, 0xF0, 0x33, 0xD0, 0x13, 0xF0, 0x15, 0x12, 0x02];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.set_quirks(Quirks { display_wait: false, ..c8.quirks() });
+        c8.enable_rewind(3 * SNAPSHOT_FRAMES);
+
+        let mut saved = Vec::new();
+        for frame in 0..2 * SNAPSHOT_FRAMES + 10 {
+            c8.keyboard[Key::K5] = frame % 2 == 1;
+            saved.push(c8.clone());
+            c8.run_frame(None).unwrap();
+        }
+        let snapshots = c8.rewind.as_ref().unwrap().frames.iter().filter(|frame| frame.snapshot.is_some()).count();
+        assert_eq!(snapshots, 3);
+
+        //Back past the last two snapshots, and into the middle of a frame past one
+        let same = |c8: &Chip8, old: &Chip8| {
+            assert_eq!((c8.pc, c8.gpregs, c8.i_reg, c8.timers.dt), (old.pc, old.gpregs, old.i_reg, old.timers.dt));
+            assert_eq!(c8.ram, old.ram);
+            assert_eq!(c8.display.raw_pixels(), old.display.raw_pixels());
+            assert_eq!(c8.keyboard[Key::K5], old.keyboard[Key::K5]);
+        };
+        assert_eq!(c8.rewind(SNAPSHOT_FRAMES + 20), SNAPSHOT_FRAMES + 20);
+        same(&c8, &saved[SNAPSHOT_FRAMES - 10]);
+        //The last LD B, V0 of the frame before
+        let mut replay = saved[SNAPSHOT_FRAMES - 11].clone();
+        let mut last_write = None;
+        for _ in 0..replay.instructions_per_frame() {
+            if replay.pc == 0x204 {
+                last_write = Some(replay.clone());
+            }
+            replay.step(None).unwrap();
+        }
+        assert!(c8.rewind_to_write(0x302));
+        same(&c8, &last_write.unwrap());
+        assert_eq!(c8.rewind(0), 0);
+        same(&c8, &saved[SNAPSHOT_FRAMES - 11]);
+        assert_eq!(c8.rewind(1), 1);
+        same(&c8, &saved[SNAPSHOT_FRAMES - 12]);
+    }
+
+    #[test]
+    fn bounded() {
+        //ADD V0, 0x01; JP 0x200
+        let rom = [0x70, 0x01, 0x12, 0x00];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.enable_rewind(2);
+
+        //Stepping never ticks the timers, so the frame is split every MAX_FRAME_STEPS steps
+        //and only the last two whole parts are kept, besides the current one
+        for _ in 0..3 * MAX_FRAME_STEPS + 3 {
+            c8.step(None).unwrap();
+        }
+        let steps: Vec<usize> = c8.rewind.as_ref().unwrap().frames.iter().map(|frame| frame.steps.len()).collect();
+        assert_eq!(steps, [MAX_FRAME_STEPS, MAX_FRAME_STEPS, 3]);
+        assert_eq!(c8.rewind_frames(), 2);
+
+        //Undoing everything left lands before step 1001, which is an ADD after 500
+        //others, so V0 = 500 wrapped to a byte
+        while c8.step_back() {}
+        assert_eq!((c8.pc, c8.gpregs[0]), (0x200, (500 % 256) as u8));
+
+        //Frames longer than the limit are split too
+        c8.enable_rewind(2);
+        c8.set_instructions_per_frame(3 * MAX_FRAME_STEPS);
+        for _ in 0..3 {
+            c8.run_frame(None).unwrap();
+        }
+        let steps: Vec<usize> = c8.rewind.as_ref().unwrap().frames.iter().map(|frame| frame.steps.len()).collect();
+        assert_eq!(steps, [MAX_FRAME_STEPS, MAX_FRAME_STEPS, 0]);
+    }
+}
\ No newline at end of file
@@ -0,0 +1,65 @@
+/// The source of random bytes for RND. Plain data, so it can be cloned and
+/// saved along with the rest of the machine.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Rng {
+    /// SplitMix64 generator. The value is the current state, which starts out as the seed.
+    Seeded(u64),
+    /// Replays `bytes` in order, wrapping around at the end
+    Scripted { bytes: Vec<u8>, pos: usize },
+}
+
+impl Rng {
+    pub fn seeded(seed: u64) -> Self {
+        Rng::Seeded(seed)
+    }
+
+    /// A generator seeded from the OS entropy source
+    pub fn from_entropy() -> Self {
+        Rng::Seeded(rand::random())
+    }
+
+    pub fn scripted(bytes: &[u8]) -> Self {
+        assert!(!bytes.is_empty(), "A scripted RNG needs at least one byte!");
+        Rng::Scripted { bytes: bytes.to_vec(), pos: 0 }
+    }
+
+    pub fn next_byte(&mut self) -> u8 {
+        match self {
+            Rng::Seeded(state) => {
+                *state = state.wrapping_add(0x9E3779B97F4A7C15);
+                let mut z = *state;
+                z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+                z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+                ((z ^ (z >> 31)) >> 56) as u8
+            },
+            Rng::Scripted { bytes, pos } => {
+                let byte = bytes[*pos];
+                *pos = (*pos + 1) % bytes.len();
+                byte
+            },
+        }
+    }
+}
+
+impl Default for Rng {
+    fn default() -> Self {
+        Rng::from_entropy()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Rng;
+
+    #[test]
+    fn replayable() {
+        let (mut a, mut b) = (Rng::seeded(0xC8), Rng::seeded(0xC8));
+        let run_a: Vec<u8> = (0..64).map(|_| a.next_byte()).collect();
+        let run_b: Vec<u8> = (0..64).map(|_| b.next_byte()).collect();
+        assert_eq!(run_a, run_b);
+
+        let mut scripted = Rng::scripted(&[1, 2, 3]);
+        let run: Vec<u8> = (0..5).map(|_| scripted.next_byte()).collect();
+        assert_eq!(run, [1, 2, 3, 1, 2]);
+    }
+}
\ No newline at end of file
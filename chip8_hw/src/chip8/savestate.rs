@@ -0,0 +1,273 @@
+//Savestate format, all integers little endian:
+//
+//  magic       b"C8ST"
+//  version     u16
+//  platform    u8, index into Platform::ALL
+//  quirks      u8, bitfield in Quirks field order starting from bit 0
+//  mem_policy  u8, 0 = wrap, 1 = clamp, 2 = fault
+//  ipf         u32
+//  halted      u8
+//  drawn       u8, whether a sprite was drawn this frame
+//  ram         u32 length, then the bytes
+//  gpregs      16 bytes
+//  i_reg, pc   u16 each
+//  sp          u8
+//  stack       16 u16s
+//  rpl         16 bytes
+//  keyboard    16 bytes, 1 for each key that is down
+//  dt, st      u8 each
+//  audio       16 pattern bytes, then the pitch as u8
+//  rng         u8 tag. 0 = seeded, followed by the u64 state.
+//              1 = scripted, followed by u32 position, u32 length and the bytes.
+//  display     u8 planes, u8 hires, u8 plane mask, then HIRES_WH pixel bytes
+
+use super::{keyboard::Key, Chip8, Display, MemoryPolicy, Platform, Quirks, Rng, HIRES_WH, STACK_LIMIT};
+use crate::StateError;
+
+const MAGIC: &[u8; 4] = b"C8ST";
+pub const STATE_VERSION: u16 = 1;
+
+type Result<T> = std::result::Result<T, StateError>;
+
+struct Reader<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> Reader<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8]> {
+        if self.data.len() < len {
+            return Err(StateError::Truncated);
+        }
+
+        let (head, tail) = self.data.split_at(len);
+        self.data = tail;
+        Ok(head)
+    }
+
+    fn array<const N: usize>(&mut self) -> Result<[u8; N]> {
+        Ok(self.bytes(N)?.try_into().expect("slice has length N"))
+    }
+
+    fn u8(&mut self) -> Result<u8> {
+        Ok(self.array::<1>()?[0])
+    }
+
+    fn bool(&mut self) -> Result<bool> {
+        Ok(self.u8()? != 0)
+    }
+
+    fn u16(&mut self) -> Result<u16> {
+        Ok(u16::from_le_bytes(self.array()?))
+    }
+
+    fn u32(&mut self) -> Result<u32> {
+        Ok(u32::from_le_bytes(self.array()?))
+    }
+
+    fn u64(&mut self) -> Result<u64> {
+        Ok(u64::from_le_bytes(self.array()?))
+    }
+}
+
+fn quirks_to_bits(quirks: &Quirks) -> u8 {
+    [quirks.vf_reset, quirks.memory, quirks.shifting, quirks.display_wait, quirks.clipping, quirks.jumping, quirks.i_overflow, quirks.lores_scaled]
+        .into_iter()
+        .enumerate()
+        .fold(0, |bits, (idx, on)| bits | (on as u8) << idx)
+}
+
+fn quirks_from_bits(bits: u8) -> Quirks {
+    let bit = |idx: u8| bits & (1 << idx) != 0;
+    Quirks {
+        vf_reset: bit(0),
+        memory: bit(1),
+        shifting: bit(2),
+        display_wait: bit(3),
+        clipping: bit(4),
+        jumping: bit(5),
+        i_overflow: bit(6),
+        lores_scaled: bit(7),
+    }
+}
+
+impl Chip8 {
+    /// Snapshot the whole machine into a versioned binary savestate
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(self.ram.len() + HIRES_WH + 0x100);
+        out.extend_from_slice(MAGIC);
+        out.extend_from_slice(&STATE_VERSION.to_le_bytes());
+
+        let platform = Platform::ALL.iter().position(|&p| p == self.platform).unwrap_or_default();
+        out.push(platform as u8);
+        out.push(quirks_to_bits(&self.quirks));
+        out.push(match self.mem_policy {
+            MemoryPolicy::Wrap => 0,
+            MemoryPolicy::Clamp => 1,
+            MemoryPolicy::Fault => 2,
+        });
+        out.extend_from_slice(&(self.ipf as u32).to_le_bytes());
+        out.push(self.halted as u8);
+        out.push(self.drawn_this_frame as u8);
+
+        out.extend_from_slice(&(self.ram.len() as u32).to_le_bytes());
+        out.extend_from_slice(&self.ram);
+        out.extend_from_slice(&self.gpregs);
+        out.extend_from_slice(&self.i_reg.to_le_bytes());
+        out.extend_from_slice(&self.pc.to_le_bytes());
+        out.push(self.sp as u8);
+        self.stack.iter().for_each(|addr| out.extend_from_slice(&addr.to_le_bytes()));
+        out.extend_from_slice(&self.rpl);
+        (0..0x10)
+            .filter_map(|idx| Key::try_from(idx).ok())
+            .for_each(|key| out.push(self.keyboard[key] as u8));
+
+        out.push(self.timers.dt);
+        out.push(self.timers.st);
+        out.extend_from_slice(&self.audio.pattern);
+        out.push(self.audio.pitch);
+
+        match &self.rng {
+            Rng::Seeded(state) => {
+                out.push(0);
+                out.extend_from_slice(&state.to_le_bytes());
+            },
+            Rng::Scripted { bytes, pos } => {
+                out.push(1);
+                out.extend_from_slice(&(*pos as u32).to_le_bytes());
+                out.extend_from_slice(&(bytes.len() as u32).to_le_bytes());
+                out.extend_from_slice(bytes);
+            },
+        }
+
+        out.push(self.display.planes() as u8);
+        out.push(self.display.is_hires() as u8);
+        out.push(self.display.plane_mask());
+        out.extend_from_slice(self.display.raw_pixels());
+
+        out
+    }
+
+    /// Restore a machine from a savestate made by `save_state`
+    pub fn load_state(state: &[u8]) -> Result<Chip8> {
+        let mut r = Reader { data: state };
+
+        if r.bytes(MAGIC.len()).ok() != Some(MAGIC.as_slice()) {
+            return Err(StateError::BadMagic);
+        }
+
+        let version = r.u16()?;
+        if version != STATE_VERSION {
+            return Err(StateError::UnsupportedVersion { found: version, supported: STATE_VERSION });
+        }
+
+        let platform = *Platform::ALL.get(r.u8()? as usize).ok_or(StateError::Invalid("platform"))?;
+        let quirks = quirks_from_bits(r.u8()?);
+        let mem_policy = match r.u8()? {
+            0 => MemoryPolicy::Wrap,
+            1 => MemoryPolicy::Clamp,
+            2 => MemoryPolicy::Fault,
+            _ => return Err(StateError::Invalid("memory policy")),
+        };
+        let ipf = r.u32()? as usize;
+        let halted = r.bool()?;
+        let drawn_this_frame = r.bool()?;
+
+        let ram_len = r.u32()? as usize;
+        if ram_len != platform.ram_size() {
+            return Err(StateError::Invalid("RAM size"));
+        }
+
+        let mut c8 = Chip8::load_rom(platform, &[]).expect("an empty ROM always fits");
+        c8.ram.copy_from_slice(r.bytes(ram_len)?);
+        c8.gpregs = r.array()?;
+        c8.i_reg = r.u16()?;
+        c8.pc = r.u16()?;
+        c8.sp = r.u8()? as usize;
+        if c8.sp > STACK_LIMIT {
+            return Err(StateError::Invalid("stack pointer"));
+        }
+        for slot in c8.stack.iter_mut() {
+            *slot = r.u16()?;
+        }
+        c8.rpl = r.array()?;
+        for idx in 0..0x10 {
+            let key = Key::try_from(idx).map_err(|_| StateError::Invalid("key"))?;
+            c8.keyboard[key] = r.bool()?;
+        }
+
+        c8.timers.dt = r.u8()?;
+        c8.timers.st = r.u8()?;
+        c8.audio.pattern = r.array()?;
+        c8.audio.pitch = r.u8()?;
+
+        c8.rng = match r.u8()? {
+            0 => Rng::Seeded(r.u64()?),
+            1 => {
+                let pos = r.u32()? as usize;
+                let len = r.u32()? as usize;
+                let bytes = r.bytes(len)?.to_vec();
+                if pos >= len {
+                    return Err(StateError::Invalid("RNG script"));
+                }
+                Rng::Scripted { bytes, pos }
+            },
+            _ => return Err(StateError::Invalid("RNG")),
+        };
+
+        let planes = r.u8()? as usize;
+        if planes != platform.instr_set().planes() {
+            return Err(StateError::Invalid("plane count"));
+        }
+        let hires = r.bool()?;
+        let plane_mask = r.u8()?;
+        c8.display = Display::from_raw(planes, hires, plane_mask, r.array()?);
+
+        c8.quirks = quirks;
+        c8.mem_policy = mem_policy;
+        c8.ipf = ipf;
+        c8.halted = halted;
+        c8.drawn_this_frame = drawn_this_frame;
+
+        Ok(c8)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn roundtrip() {
+        //LD V0, 0x12; RND V1, 0xFF; LD F, V0; DRW V0, V1, 5; JP 0x200
+        let rom = [0x60, 0x12, 0xC1, 0xFF, 0xF0, 0x29, 0xD0, 0x15, 0x12, 0x00];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        c8.rng = Rng::seeded(0xC8);
+        c8.run_frame(None).unwrap();
+
+        let state = c8.save_state();
+        let mut restored = Chip8::load_state(&state).unwrap();
+        assert_eq!(restored.save_state(), state);
+
+        for _ in 0..10 {
+            c8.run_frame(None).unwrap();
+            restored.run_frame(None).unwrap();
+        }
+        assert_eq!(restored.save_state(), c8.save_state());
+
+        assert_eq!(Chip8::load_state(&state[..100]).err(), Some(StateError::Truncated));
+    }
+
+    #[test]
+    fn versions() {
+        let state = Chip8::load_rom(Platform::Modern, &[]).unwrap().save_state();
+
+        //Only the current version is read, older and newer ones are both rejected
+        for found in [STATE_VERSION - 1, STATE_VERSION + 1] {
+            let mut other = state.clone();
+            other[4..6].copy_from_slice(&found.to_le_bytes());
+            let err = Chip8::load_state(&other).err().unwrap();
+            assert_eq!(err, StateError::UnsupportedVersion { found, supported: STATE_VERSION });
+            assert_eq!(err.to_string(), format!("Savestate version {found} is not supported, expected version {STATE_VERSION}"));
+        }
+    }
+}
\ No newline at end of file
@@ -1,28 +1,16 @@
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
-#[derive(Debug)]
+#[derive(Debug, Default, Clone)]
 pub struct Timers {
     pub(super) dt: u8,
     pub(super) st: u8,
-    last_tick: Instant,
 }
 
 impl Timers {
-    const HZ_60: u128 = 1_000_000_000 / 60;
-
+    /// Count both timers down by one. Called once per 60 Hz frame.
     pub(super) fn tick(&mut self) {
-        let elapsed = self.last_tick.elapsed();
-        if elapsed.as_nanos() >= Timers::HZ_60 {
-            if self.dt > 0 {
-                self.dt -= 1;
-            }
-
-            if self.st > 0 {
-                self.st -= 1;
-            }
-
-            self.last_tick = Instant::now();
-        }
+        self.dt = self.dt.saturating_sub(1);
+        self.st = self.st.saturating_sub(1);
     }
 
     pub fn delay(&self) -> u8 {
@@ -34,12 +22,56 @@ impl Timers {
     }
 }
 
-impl Default for Timers {
+/// Wall-clock driver for hosts that don't have their own 60 Hz loop.
+/// Each call to `frames_due` reports how many frames have elapsed since the last.
+#[derive(Debug)]
+pub struct WallClock {
+    last_frame: Instant,
+}
+
+impl WallClock {
+    pub const FRAME: Duration = Duration::from_nanos(1_000_000_000 / 60);
+
+    pub fn frames_due(&mut self) -> u32 {
+        let mut frames = 0;
+        while self.last_frame.elapsed() >= Self::FRAME {
+            self.last_frame += Self::FRAME;
+            frames += 1;
+        }
+        frames
+    }
+}
+
+impl Default for WallClock {
     fn default() -> Self {
-        Timers {
-            dt: 0,
-            st: 0,
-            last_tick: Instant::now(),
+        WallClock {
+            last_frame: Instant::now(),
         }
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tick() {
+        let mut timers = Timers { dt: 2, st: 1 };
+        timers.tick();
+        assert_eq!((timers.delay(), timers.sound()), (1, 0));
+        timers.tick();
+        timers.tick();
+        assert_eq!((timers.delay(), timers.sound()), (0, 0));
+    }
+
+    #[test]
+    fn wall_clock() {
+        let mut clock = WallClock { last_frame: Instant::now() - WallClock::FRAME * 3 - WallClock::FRAME / 2 };
+        assert_eq!(clock.frames_due(), 3);
+        assert_eq!(clock.frames_due(), 0);
+
+        //The leftover half frame carries over instead of being dropped
+        std::thread::sleep(WallClock::FRAME / 2);
+        assert_eq!(clock.frames_due(), 1);
+    }
 }
\ No newline at end of file
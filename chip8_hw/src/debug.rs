@@ -0,0 +1,305 @@
+//Breakpoints and watchpoints around `Chip8::step`.
+//
+//Execution and instruction breakpoints are checked before an instruction runs, so the
+//machine stops with PC on it. Memory and register watchpoints can only be checked after
+//the instruction ran. Resuming from an execution or instruction breakpoint runs the
+//instruction it stopped on instead of stopping there again.
+
+use std::fmt;
+use std::ops::RangeInclusive;
+use std::str::FromStr;
+
+use chip8_decode::instructions::Instr;
+use chip8_decode::syntax::Mnemonic;
+use shared::reg::GPReg;
+
+use crate::chip8::{keyboard::Key, Chip8, FrameStep};
+use crate::Result;
+
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Access {
+    Read,
+    Write,
+}
+
+/// One byte an instruction read or wrote through I, after the memory policy resolved it
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub struct MemAccess {
+    pub kind: Access,
+    pub addr: u16,
+}
+
+/// A register a watchpoint can follow
+#[derive(Copy, Clone, Hash, Eq, PartialEq, Debug)]
+pub enum Register {
+    V(GPReg),
+    I,
+}
+
+impl Register {
+    pub fn get(&self, c8: &Chip8) -> u16 {
+        match *self {
+            Register::V(reg) => c8.gpregs[reg] as u16,
+            Register::I => c8.i_reg,
+        }
+    }
+}
+
+impl fmt::Display for Register {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Register::V(reg) => write!(f, "{reg:?}"),
+            Register::I => write!(f, "I"),
+        }
+    }
+}
+
+impl FromStr for Register {
+    type Err = String;
+
+    /// `V0` to `VF` or `I`, in any case
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let upper = s.to_uppercase();
+        if upper == "I" {
+            return Ok(Register::I);
+        }
+        upper.strip_prefix('V')
+            .filter(|idx| idx.len() == 1)
+            .and_then(|idx| u8::from_str_radix(idx, 16).ok())
+            .and_then(GPReg::indexed)
+            .map(Register::V)
+            .ok_or_else(|| format!("Unknown register: {s}"))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Breakpoint {
+    /// Stop before executing the instruction at this address
+    Exec(u16),
+    /// Stop after an instruction reads from the range through I
+    Read(RangeInclusive<u16>),
+    /// Stop after an instruction writes to the range through I
+    Write(RangeInclusive<u16>),
+    /// Stop after an instruction changes the register's value
+    Register(Register),
+    /// Stop before executing any instruction with this mnemonic, e.g. every form of `LD`
+    Instr(Mnemonic),
+}
+
+/// What made a breakpoint fire
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum Event {
+    Exec,
+    Instr(Instr),
+    Read { addr: u16, value: u8 },
+    Write { addr: u16, value: u8 },
+    Register { reg: Register, old: u16, new: u16 },
+}
+
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub struct Hit {
+    /// The id `Debugger::add` returned for the breakpoint
+    pub id: usize,
+    /// The instruction that was about to run, or that triggered a watchpoint
+    pub pc: u16,
+    pub event: Event,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Step {
+    /// The instruction ran and nothing fired
+    Executed(Instr),
+    /// Every breakpoint that fired, in id order. For watchpoints the instruction has already run.
+    Break(Vec<Hit>),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Entry {
+    id: usize,
+    enabled: bool,
+    breakpoint: Breakpoint,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+    entries: Vec<Entry>,
+    next_id: usize,
+    //Set when stopped before an instruction, so the next step runs it
+    resume_at: Option<u16>,
+}
+
+impl Debugger {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a breakpoint and return its id. Ids are never reused.
+    pub fn add(&mut self, breakpoint: Breakpoint) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        self.entries.push(Entry { id, enabled: true, breakpoint });
+        id
+    }
+
+    pub fn remove(&mut self, id: usize) -> Option<Breakpoint> {
+        let idx = self.entries.iter().position(|entry| entry.id == id)?;
+        Some(self.entries.remove(idx).breakpoint)
+    }
+
+    /// Enable or disable a breakpoint without forgetting it. False if there's no such id.
+    pub fn set_enabled(&mut self, id: usize, enabled: bool) -> bool {
+        match self.entries.iter_mut().find(|entry| entry.id == id) {
+            Some(entry) => {
+                entry.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Every breakpoint with its id and whether it's enabled
+    pub fn breakpoints(&self) -> impl Iterator<Item = (usize, &Breakpoint, bool)> + '_ {
+        self.entries.iter().map(|entry| (entry.id, &entry.breakpoint, entry.enabled))
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &Entry> + '_ {
+        self.entries.iter().filter(|entry| entry.enabled)
+    }
+
+    /// Execute one instruction like `Chip8::step`, unless a breakpoint stops it
+    pub fn step(&mut self, c8: &mut Chip8, next_key: Option<Key>) -> Result<Step> {
+        Ok(match self.step_checked(c8, next_key)? {
+            (Some(instr), hits) if hits.is_empty() => Step::Executed(instr),
+            (_, hits) => Step::Break(hits),
+        })
+    }
+
+    //The instruction, unless a breakpoint stopped before it, and every breakpoint that fired
+    fn step_checked(&mut self, c8: &mut Chip8, next_key: Option<Key>) -> Result<(Option<Instr>, Vec<Hit>)> {
+        let pc = c8.pc;
+        if self.resume_at.take() != Some(pc) {
+            let hits = self.check_before(c8);
+            if !hits.is_empty() {
+                self.resume_at = Some(pc);
+                return Ok((None, hits));
+            }
+        }
+
+        let before: Vec<(usize, Register, u16)> = self.enabled()
+            .filter_map(|entry| match entry.breakpoint {
+                Breakpoint::Register(reg) => Some((entry.id, reg, reg.get(c8))),
+                _ => None,
+            })
+            .collect();
+
+        let instr = c8.step(next_key)?;
+
+        let mut hits = Vec::new();
+        for entry in self.enabled() {
+            let (kind, range) = match &entry.breakpoint {
+                Breakpoint::Read(range) => (Access::Read, range),
+                Breakpoint::Write(range) => (Access::Write, range),
+                _ => continue,
+            };
+            let access = c8.accesses().iter().find(|access| access.kind == kind && range.contains(&access.addr));
+            if let Some(&MemAccess { addr, .. }) = access {
+                let value = c8.ram[addr as usize];
+                let event = match kind {
+                    Access::Read => Event::Read { addr, value },
+                    Access::Write => Event::Write { addr, value },
+                };
+                hits.push(Hit { id: entry.id, pc, event });
+            }
+        }
+        for (id, reg, old) in before {
+            let new = reg.get(c8);
+            if new != old {
+                hits.push(Hit { id, pc, event: Event::Register { reg, old, new } });
+            }
+        }
+        hits.sort_by_key(|hit| hit.id);
+
+        Ok((Some(instr), hits))
+    }
+
+    //Execution and instruction breakpoints for the instruction at PC
+    fn check_before(&self, c8: &Chip8) -> Vec<Hit> {
+        let pc = c8.pc;
+        let mut hits = Vec::new();
+        let mut instr = None;
+        for entry in self.enabled() {
+            match &entry.breakpoint {
+                Breakpoint::Exec(addr) if *addr == pc => hits.push(Hit { id: entry.id, pc, event: Event::Exec }),
+                Breakpoint::Instr(mnemonic) => {
+                    //A fault here is left for `Chip8::step` to report
+                    let Ok(instr) = *instr.get_or_insert_with(|| c8.fetch()) else {
+                        continue;
+                    };
+                    if instr.mnemonic() == *mnemonic {
+                        hits.push(Hit { id: entry.id, pc, event: Event::Instr(instr) });
+                    }
+                },
+                _ => {},
+            }
+        }
+        hits
+    }
+
+    /// Like `Chip8::run_frame`, but stops as soon as a breakpoint fires. The timers only
+    /// tick once the frame's instructions have all run, so stopping and resuming
+    /// doesn't change the timing.
+    pub fn run_frame(&mut self, c8: &mut Chip8, next_key: Option<Key>) -> Result<Option<Vec<Hit>>> {
+        self.run(c8, next_key, usize::MAX)
+    }
+
+    /// Like `run_frame`, but returns after at most `limit` instructions. The next call
+    /// carries on with the same frame, so the timers tick at the same point either way.
+    pub fn run(&mut self, c8: &mut Chip8, next_key: Option<Key>, limit: usize) -> Result<Option<Vec<Hit>>> {
+        let mut ran = 0;
+        let stopped = c8.run_frame_with(next_key, |c8| {
+            if ran == limit {
+                return Ok(FrameStep::Stop(None, None));
+            }
+            ran += 1;
+            Ok(match self.step_checked(c8, next_key)? {
+                (Some(instr), hits) if hits.is_empty() => FrameStep::Ran(instr),
+                (instr, hits) => FrameStep::Stop(instr, Some(hits)),
+            })
+        })?;
+        Ok(stopped.flatten())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Platform;
+
+    #[test]
+    fn breakpoints() {
+        //LD I, 0x300; LD V0, 0x07; LD B, V0; DRW V0, V0, 1
+        let rom = [0xA3, 0x00, 0x60, 0x07, 0xF0, 0x33, 0xD0, 0x01];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        let mut dbg = Debugger::new();
+        let exec = dbg.add(Breakpoint::Exec(0x202));
+        let reg = dbg.add(Breakpoint::Register(Register::V(GPReg::V0)));
+        let write = dbg.add(Breakpoint::Write(0x301..=0x301));
+        let drw = dbg.add(Breakpoint::Instr(Mnemonic::DRW));
+
+        assert!(matches!(dbg.step(&mut c8, None), Ok(Step::Executed(Instr::LDI(_)))));
+        assert_eq!(dbg.step(&mut c8, None), Ok(Step::Break(vec![Hit { id: exec, pc: 0x202, event: Event::Exec }])));
+        assert_eq!(c8.pc, 0x202);
+        assert_eq!(dbg.step(&mut c8, None), Ok(Step::Break(vec![
+            Hit { id: reg, pc: 0x202, event: Event::Register { reg: Register::V(GPReg::V0), old: 0, new: 7 } },
+        ])));
+        assert_eq!(dbg.step(&mut c8, None), Ok(Step::Break(vec![
+            Hit { id: write, pc: 0x204, event: Event::Write { addr: 0x301, value: 0 } },
+        ])));
+        assert!(matches!(dbg.step(&mut c8, None), Ok(Step::Break(hits)) if hits[0].id == drw && c8.pc == 0x206));
+        assert!(matches!(dbg.step(&mut c8, None), Ok(Step::Executed(Instr::DRW(..)))));
+    }
+}
\ No newline at end of file
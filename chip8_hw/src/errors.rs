@@ -0,0 +1,115 @@
+use std::fmt;
+
+use chip8_decode::instructions::{DecodeErr, Instr};
+use shared::reg::GPReg;
+
+use crate::chip8::InstrSet;
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+/// A fault raised by `Chip8::step`. `pc` is always the address of the
+/// instruction that faulted, and `opcode` its first raw word. The machine is
+/// left with PC still on that instruction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Error {
+    /// PC points at or past the last byte of RAM, so no opcode can be fetched
+    PcOutOfBounds { pc: u16 },
+    /// The word at PC is not a valid instruction
+    Decode { pc: u16, opcode: u16, err: DecodeErr },
+    /// The instruction decoded, but is not part of the machine's instruction set
+    Unsupported { pc: u16, opcode: u16, instr: Instr, instr_set: InstrSet },
+    /// CALL with every stack slot in use
+    StackOverflow { pc: u16, opcode: u16 },
+    /// RET with an empty stack
+    StackUnderflow { pc: u16, opcode: u16 },
+    /// SKP or SKNP with a value in `reg` that doesn't name a key
+    InvalidKey { pc: u16, opcode: u16, reg: GPReg, value: u8 },
+    /// An access through I reached `addr`, past the end of RAM, under `MemoryPolicy::Fault`
+    MemoryFault { pc: u16, opcode: u16, addr: usize },
+}
+
+impl Error {
+    pub fn pc(&self) -> u16 {
+        match *self {
+            Error::PcOutOfBounds { pc }
+            | Error::Decode { pc, .. }
+            | Error::Unsupported { pc, .. }
+            | Error::StackOverflow { pc, .. }
+            | Error::StackUnderflow { pc, .. }
+            | Error::InvalidKey { pc, .. }
+            | Error::MemoryFault { pc, .. } => pc,
+        }
+    }
+
+    /// The raw opcode that faulted. None if PC was out of bounds.
+    pub fn opcode(&self) -> Option<u16> {
+        match *self {
+            Error::PcOutOfBounds { .. } => None,
+            Error::Decode { opcode, .. }
+            | Error::Unsupported { opcode, .. }
+            | Error::StackOverflow { opcode, .. }
+            | Error::StackUnderflow { opcode, .. }
+            | Error::InvalidKey { opcode, .. }
+            | Error::MemoryFault { opcode, .. } => Some(opcode),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::PcOutOfBounds { pc } => write!(f, "PC beyond RAM limit! pc = 0x{pc:04X}"),
+            Error::Decode { pc, err, .. } => write!(f, "Failed to decode instruction at 0x{pc:04X}: {err}"),
+            Error::Unsupported { pc, instr, instr_set, .. } => write!(f, "{instr:?} is not supported by {instr_set:?}. pc = 0x{pc:04X}"),
+            Error::StackOverflow { pc, .. } => write!(f, "Stack overflow! pc = 0x{pc:04X}"),
+            Error::StackUnderflow { pc, .. } => write!(f, "Stack underflow! pc = 0x{pc:04X}"),
+            Error::InvalidKey { pc, reg, value, .. } => write!(f, "Invalid key idx {value} in {reg:?}. pc = 0x{pc:04X}"),
+            Error::MemoryFault { pc, addr, .. } => write!(f, "Memory access out of bounds at 0x{addr:04X}. pc = 0x{pc:04X}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// Why a ROM could not be loaded
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RomError {
+    /// The ROM doesn't fit in RAM after 0x200, which leaves room for `max` bytes
+    TooLarge { size: usize, max: usize },
+}
+
+impl fmt::Display for RomError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RomError::TooLarge { size, max } => write!(f, "ROM is too large: {size} bytes, the most that fits is {max}"),
+        }
+    }
+}
+
+impl std::error::Error for RomError {}
+
+/// Why a savestate could not be restored
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StateError {
+    /// The data doesn't start with the savestate magic
+    BadMagic,
+    /// The savestate was written by a format version other than the one this build reads
+    UnsupportedVersion { found: u16, supported: u16 },
+    /// The data ended before the savestate did
+    Truncated,
+    /// A field held a value that can't be restored
+    Invalid(&'static str),
+}
+
+impl fmt::Display for StateError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StateError::BadMagic => write!(f, "Not a chip8 savestate"),
+            StateError::UnsupportedVersion { found, supported } => write!(f, "Savestate version {found} is not supported, expected version {supported}"),
+            StateError::Truncated => write!(f, "Savestate is truncated"),
+            StateError::Invalid(field) => write!(f, "Savestate has an invalid {field}"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
\ No newline at end of file
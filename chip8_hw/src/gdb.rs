@@ -0,0 +1,499 @@
+//A GDB Remote Serial Protocol stub, so gdb or another RSP frontend can drive a `Chip8`.
+//
+//The register file, in `g` packet order, is V0-VF (1 byte each), I and PC (2 bytes each),
+//SP (1 byte), then the 16 stack slots (2 bytes each). Multi-byte registers are little
+//endian, like every gdb target. The memory map is the machine's RAM.
+//
+//Breakpoints map onto `Debugger`: Z0/Z1 are execution breakpoints, Z2 write, Z3 read
+//and Z4 access watchpoints.
+
+use std::collections::HashMap;
+use std::io::{self, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+
+use crate::chip8::{Chip8, STACK_LIMIT};
+use crate::debug::{Breakpoint, Debugger, Event, Step};
+use crate::Error;
+
+/// Number of registers in the register file, see the module docs for the layout
+pub const REGISTERS: usize = 0x10 + 3 + STACK_LIMIT;
+
+static TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<feature name="org.chip8.core">
+<reg name="v0" bitsize="8" regnum="0"/><reg name="v1" bitsize="8"/><reg name="v2" bitsize="8"/><reg name="v3" bitsize="8"/>
+<reg name="v4" bitsize="8"/><reg name="v5" bitsize="8"/><reg name="v6" bitsize="8"/><reg name="v7" bitsize="8"/>
+<reg name="v8" bitsize="8"/><reg name="v9" bitsize="8"/><reg name="va" bitsize="8"/><reg name="vb" bitsize="8"/>
+<reg name="vc" bitsize="8"/><reg name="vd" bitsize="8"/><reg name="ve" bitsize="8"/><reg name="vf" bitsize="8"/>
+<reg name="i" bitsize="16" type="data_ptr"/><reg name="pc" bitsize="16" type="code_ptr"/><reg name="sp" bitsize="8"/>
+<reg name="s0" bitsize="16"/><reg name="s1" bitsize="16"/><reg name="s2" bitsize="16"/><reg name="s3" bitsize="16"/>
+<reg name="s4" bitsize="16"/><reg name="s5" bitsize="16"/><reg name="s6" bitsize="16"/><reg name="s7" bitsize="16"/>
+<reg name="s8" bitsize="16"/><reg name="s9" bitsize="16"/><reg name="s10" bitsize="16"/><reg name="s11" bitsize="16"/>
+<reg name="s12" bitsize="16"/><reg name="s13" bitsize="16"/><reg name="s14" bitsize="16"/><reg name="s15" bitsize="16"/>
+</feature>
+</target>"#;
+
+//What the connection should do after a packet
+#[derive(Debug, PartialEq, Eq)]
+enum Response {
+    Reply(String),
+    /// Run until a breakpoint or an interrupt, then send the stop reply
+    Resume,
+    /// Acknowledge and close the connection
+    Detach,
+    /// Close the connection without a reply
+    Kill,
+}
+
+//A packet as read off the wire
+#[derive(Debug, PartialEq, Eq)]
+enum Packet {
+    Data(String),
+    //The checksum didn't match, the frontend resends it after a NAK
+    Corrupt,
+}
+
+pub struct GdbStub {
+    pub c8: Chip8,
+    pub dbg: Debugger,
+    //Debugger ids for each (Z type, addr, len), Z4 adds two
+    points: HashMap<(u8, u16, u16), Vec<usize>>,
+}
+
+impl GdbStub {
+    pub fn new(c8: Chip8) -> Self {
+        Self { c8, dbg: Debugger::new(), points: HashMap::new() }
+    }
+
+    /// Wait for one frontend to connect on `addr` and serve it until it detaches
+    pub fn listen(&mut self, addr: impl ToSocketAddrs) -> io::Result<()> {
+        self.accept(&TcpListener::bind(addr)?)
+    }
+
+    /// Like `listen`, on a listener that is already bound
+    pub fn accept(&mut self, listener: &TcpListener) -> io::Result<()> {
+        let (stream, _) = listener.accept()?;
+        self.serve(stream)
+    }
+
+    pub fn serve(&mut self, stream: TcpStream) -> io::Result<()> {
+        stream.set_nodelay(true)?;
+        let mut reader = BufReader::new(stream.try_clone()?);
+        let mut writer = stream;
+
+        while let Some(packet) = read_packet(&mut reader)? {
+            let Packet::Data(packet) = packet else {
+                writer.write_all(b"-")?;
+                writer.flush()?;
+                continue;
+            };
+            writer.write_all(b"+")?;
+            match self.handle(&packet) {
+                Response::Reply(reply) => write_packet(&mut writer, &reply)?,
+                Response::Resume => {
+                    let reply = self.resume(|| interrupted(reader.get_ref()))?;
+                    write_packet(&mut writer, &reply)?;
+                },
+                Response::Detach => {
+                    write_packet(&mut writer, "OK")?;
+                    break;
+                },
+                Response::Kill => {
+                    writer.flush()?;
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn handle(&mut self, packet: &str) -> Response {
+        let reply = |text: &str| Response::Reply(text.to_string());
+        let (command, args) = packet.split_at(packet.chars().next().map_or(0, char::len_utf8));
+
+        match command {
+            "?" => reply("S05"),
+            "g" => Response::Reply((0..REGISTERS).map(|reg| self.read_register(reg)).collect()),
+            "G" => {
+                let mut rest = args;
+                for reg in 0..REGISTERS {
+                    let width = register_width(reg) * 2;
+                    if rest.len() < width || self.write_register(reg, &rest[..width]).is_none() {
+                        return reply("E01");
+                    }
+                    rest = &rest[width..];
+                }
+                reply("OK")
+            },
+            "p" => match usize::from_str_radix(args, 16) {
+                Ok(reg) if reg < REGISTERS => Response::Reply(self.read_register(reg)),
+                _ => reply("E01"),
+            },
+            "P" => {
+                let written = args.split_once('=').and_then(|(reg, value)| {
+                    let reg = usize::from_str_radix(reg, 16).ok().filter(|&reg| reg < REGISTERS)?;
+                    self.write_register(reg, value)
+                });
+                reply(if written.is_some() { "OK" } else { "E01" })
+            },
+            "m" => match parse_addr_len(args).and_then(|(addr, len)| self.read_memory(addr, len)) {
+                Some(bytes) => Response::Reply(bytes),
+                None => reply("E01"),
+            },
+            "M" => {
+                let written = args.split_once(':').and_then(|(range, data)| {
+                    let (addr, len) = parse_addr_len(range)?;
+                    let bytes = decode_hex(data).filter(|bytes| bytes.len() == len)?;
+                    self.write_memory(addr, &bytes)
+                });
+                reply(if written.is_some() { "OK" } else { "E01" })
+            },
+            "c" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.c8.pc = addr;
+                }
+                Response::Resume
+            },
+            "s" => {
+                if let Some(addr) = parse_hex(args) {
+                    self.c8.pc = addr;
+                }
+                Response::Reply(self.single_step())
+            },
+            "Z" | "z" => {
+                let Some((kind, addr, len)) = parse_point(args) else {
+                    return reply("E01");
+                };
+                if command == "Z" {
+                    self.insert_point(kind, addr, len)
+                } else {
+                    self.remove_point(kind, addr, len)
+                }
+            },
+            "H" => reply("OK"),
+            "D" => Response::Detach,
+            "k" => Response::Kill,
+            "q" => self.query(args),
+            //Anything else is unsupported, which the protocol signals with an empty reply
+            _ => reply(""),
+        }
+    }
+
+    fn query(&self, query: &str) -> Response {
+        if query.starts_with("Supported") {
+            return Response::Reply("PacketSize=1000;qXfer:features:read+".into());
+        }
+        if let Some(request) = query.strip_prefix("Xfer:features:read:target.xml:") {
+            let Some((offset, len)) = parse_addr_len(request) else {
+                return Response::Reply("E01".into());
+            };
+            let offset = (offset as usize).min(TARGET_XML.len());
+            let chunk = &TARGET_XML[offset..(offset + len).min(TARGET_XML.len())];
+            let more = if offset + chunk.len() < TARGET_XML.len() { 'm' } else { 'l' };
+            return Response::Reply(format!("{more}{chunk}"));
+        }
+        Response::Reply(match query {
+            "Attached" => "1".into(),
+            "C" => "QC1".into(),
+            "fThreadInfo" => "m1".into(),
+            "sThreadInfo" => "l".into(),
+            _ => String::new(),
+        })
+    }
+
+    fn insert_point(&mut self, kind: u8, addr: u16, len: u16) -> Response {
+        let range = addr..=addr.saturating_add(len.max(1) - 1);
+        let breakpoints = match kind {
+            0 | 1 => vec![Breakpoint::Exec(addr)],
+            2 => vec![Breakpoint::Write(range)],
+            3 => vec![Breakpoint::Read(range)],
+            4 => vec![Breakpoint::Write(range.clone()), Breakpoint::Read(range)],
+            _ => return Response::Reply(String::new()),
+        };
+
+        let ids: Vec<usize> = breakpoints.into_iter().map(|breakpoint| self.dbg.add(breakpoint)).collect();
+        self.points.entry((kind, addr, len)).or_default().extend(ids);
+        Response::Reply("OK".into())
+    }
+
+    fn remove_point(&mut self, kind: u8, addr: u16, len: u16) -> Response {
+        if kind > 4 {
+            return Response::Reply(String::new());
+        }
+        for id in self.points.remove(&(kind, addr, len)).unwrap_or_default() {
+            self.dbg.remove(id);
+        }
+        Response::Reply("OK".into())
+    }
+
+    //Step once, even if an execution breakpoint sits on the current instruction
+    fn single_step(&mut self) -> String {
+        let mut result = self.dbg.step(&mut self.c8, None);
+        if let Ok(Step::Break(hits)) = &result {
+            if hits.iter().all(|hit| matches!(hit.event, Event::Exec | Event::Instr(_))) {
+                result = self.dbg.step(&mut self.c8, None);
+            }
+        }
+        stop_reply(result.map(|_| ()), self.c8.is_halted())
+    }
+
+    //Run frame by frame until a breakpoint fires, the machine stops or `interrupted` says so
+    fn resume(&mut self, mut interrupted: impl FnMut() -> bool) -> io::Result<String> {
+        loop {
+            if self.c8.is_halted() {
+                return Ok(stop_reply(Ok(()), true));
+            }
+            match self.dbg.run_frame(&mut self.c8, None) {
+                Ok(Some(_)) => return Ok(stop_reply(Ok(()), false)),
+                Ok(None) => {},
+                Err(e) => return Ok(stop_reply(Err(e), false)),
+            }
+            if interrupted() {
+                return Ok("S02".into());
+            }
+        }
+    }
+
+    fn read_register(&self, reg: usize) -> String {
+        let c8 = &self.c8;
+        match reg {
+            0x0..=0xF => format!("{:02x}", c8.gpregs[reg]),
+            0x10 => le16(c8.i_reg),
+            0x11 => le16(c8.pc),
+            0x12 => format!("{:02x}", c8.sp),
+            _ => le16(c8.stack[reg - 0x13]),
+        }
+    }
+
+    fn write_register(&mut self, reg: usize, hex: &str) -> Option<()> {
+        let bytes = decode_hex(hex).filter(|bytes| bytes.len() == register_width(reg))?;
+        let value = bytes.iter().rev().fold(0u16, |acc, &byte| acc << 8 | byte as u16);
+        let c8 = &mut self.c8;
+        match reg {
+            0x0..=0xF => c8.gpregs[reg] = value as u8,
+            0x10 => c8.i_reg = value,
+            0x11 => c8.pc = value,
+            0x12 if (value as usize) <= STACK_LIMIT => c8.sp = value as usize,
+            0x12 => return None,
+            _ => c8.stack[reg - 0x13] = value,
+        }
+        Some(())
+    }
+
+    fn read_memory(&self, addr: u16, len: usize) -> Option<String> {
+        let start = addr as usize;
+        let bytes = self.c8.ram.get(start..(start + len).min(self.c8.ram.len()))?;
+        Some(bytes.iter().map(|byte| format!("{byte:02x}")).collect())
+    }
+
+    fn write_memory(&mut self, addr: u16, bytes: &[u8]) -> Option<()> {
+        let start = addr as usize;
+        self.c8.ram.get_mut(start..start + bytes.len())?.copy_from_slice(bytes);
+        Some(())
+    }
+}
+
+fn register_width(reg: usize) -> usize {
+    match reg {
+        0x0..=0xF | 0x12 => 1,
+        _ => 2,
+    }
+}
+
+//The stop reply: S05 (SIGTRAP) for a breakpoint or step, W00 once the machine halted,
+//S04 (SIGILL) or S0B (SIGSEGV) for a fault
+fn stop_reply(result: crate::Result<()>, halted: bool) -> String {
+    match result {
+        Ok(()) if halted => "W00".into(),
+        Ok(()) => "S05".into(),
+        Err(Error::Decode { .. } | Error::Unsupported { .. }) => "S04".into(),
+        Err(_) => "S0B".into(),
+    }
+}
+
+fn le16(value: u16) -> String {
+    format!("{:02x}{:02x}", value & 0xFF, value >> 8)
+}
+
+fn parse_hex(text: &str) -> Option<u16> {
+    u16::from_str_radix(text, 16).ok()
+}
+
+//"addr,len" in hex
+fn parse_addr_len(text: &str) -> Option<(u16, usize)> {
+    let (addr, len) = text.split_once(',')?;
+    Some((parse_hex(addr)?, usize::from_str_radix(len, 16).ok()?))
+}
+
+//"type,addr,kind" in hex, where kind is the length for watchpoints
+fn parse_point(text: &str) -> Option<(u8, u16, u16)> {
+    let mut fields = text.split(',');
+    let kind = fields.next()?.parse().ok()?;
+    let addr = parse_hex(fields.next()?)?;
+    let len = parse_hex(fields.next()?)?;
+    Some((kind, addr, len))
+}
+
+fn decode_hex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len()).step_by(2)
+        .map(|idx| u8::from_str_radix(text.get(idx..idx + 2)?, 16).ok())
+        .collect()
+}
+
+fn checksum(data: &str) -> u8 {
+    data.bytes().fold(0u8, |sum, byte| sum.wrapping_add(byte))
+}
+
+fn write_packet(out: &mut impl Write, data: &str) -> io::Result<()> {
+    write!(out, "${data}#{:02x}", checksum(data))?;
+    out.flush()
+}
+
+//The next `$data#cs` packet, skipping acks and stray interrupts. None once the frontend hangs up.
+fn read_packet(input: &mut impl Read) -> io::Result<Option<Packet>> {
+    let mut byte = [0u8];
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'$' {
+            break;
+        }
+    }
+
+    let mut data = Vec::new();
+    loop {
+        if input.read(&mut byte)? == 0 {
+            return Ok(None);
+        }
+        if byte[0] == b'#' {
+            break;
+        }
+        data.push(byte[0]);
+    }
+    let mut sum = [0u8; 2];
+    input.read_exact(&mut sum)?;
+
+    let data = String::from_utf8_lossy(&data).into_owned();
+    let valid = std::str::from_utf8(&sum).ok()
+        .and_then(|sum| u8::from_str_radix(sum, 16).ok())
+        .is_some_and(|sum| sum == checksum(&data));
+    Ok(Some(if valid { Packet::Data(data) } else { Packet::Corrupt }))
+}
+
+//Whether the frontend sent a break (0x03) while the machine was running
+fn interrupted(stream: &TcpStream) -> bool {
+    let mut byte = [0u8];
+    if stream.set_nonblocking(true).is_err() {
+        return false;
+    }
+    let peeked = stream.peek(&mut byte);
+    let _ = stream.set_nonblocking(false);
+
+    match peeked {
+        Ok(1) if byte[0] == 0x03 => {
+            let _ = (&*stream).read_exact(&mut byte);
+            true
+        },
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Platform;
+
+    #[test]
+    fn packets() {
+        //LD V0, 0x07; LD I, 0x300; JP 0x204
+        let rom = [0x60, 0x07, 0xA3, 0x00, 0x12, 0x04];
+        let mut stub = GdbStub::new(Chip8::load_rom(Platform::Modern, &rom).unwrap());
+        let reply = |stub: &mut GdbStub, packet| match stub.handle(packet) {
+            Response::Reply(reply) => reply,
+            other => panic!("{other:?}"),
+        };
+
+        assert_eq!(reply(&mut stub, "s"), "S05");
+        assert_eq!(reply(&mut stub, "p0"), "07");
+        assert_eq!(reply(&mut stub, "p11"), "0202");
+        assert_eq!(reply(&mut stub, "m200,4"), "6007a300");
+        assert_eq!(reply(&mut stub, "M300,2:abcd"), "OK");
+        assert_eq!(stub.c8.ram[0x301], 0xCD);
+        assert_eq!(reply(&mut stub, "P10=3412"), "OK");
+        assert_eq!(stub.c8.i_reg, 0x1234);
+        assert_eq!(reply(&mut stub, "g").len(), (0x10 + 2 * 2 + 1 + 2 * STACK_LIMIT) * 2);
+
+        assert_eq!(reply(&mut stub, "Z0,204,2"), "OK");
+        assert_eq!(stub.handle("c"), Response::Resume);
+        assert_eq!(stub.resume(|| false).unwrap(), "S05");
+        assert_eq!(stub.c8.pc, 0x204);
+        assert_eq!(reply(&mut stub, "z0,204,2"), "OK");
+        assert_eq!(stub.resume(|| false).unwrap(), "W00");
+
+        let mut framed = Vec::new();
+        write_packet(&mut framed, "OK").unwrap();
+        assert_eq!(framed, b"$OK#9a");
+        assert_eq!(read_packet(&mut &b"+$m200,4#5f"[..]).unwrap(), Some(Packet::Data("m200,4".into())));
+        assert_eq!(read_packet(&mut &b"$m200,4#xx"[..]).unwrap(), Some(Packet::Corrupt));
+        assert_eq!(stub.handle("k"), Response::Kill);
+    }
+
+    #[test]
+    fn loopback() {
+        //LD V0, 0x07
+        let rom = [0x60, 0x07];
+        let mut stub = GdbStub::new(Chip8::load_rom(Platform::Modern, &rom).unwrap());
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let client = std::thread::spawn(move || {
+            let mut stream = TcpStream::connect(addr).unwrap();
+            let mut byte = [0u8];
+            let mut next = |stream: &mut TcpStream| {
+                stream.read_exact(&mut byte).unwrap();
+                byte[0]
+            };
+            //The ack, then the reply, checking its checksum like gdb does
+            let mut exchange = |stream: &mut TcpStream, packet: &str| {
+                write_packet(stream, packet).unwrap();
+                assert_eq!(next(stream), b'+');
+                assert_eq!(next(stream), b'$');
+                let mut data = String::new();
+                loop {
+                    match next(stream) {
+                        b'#' => break,
+                        byte => data.push(byte as char),
+                    }
+                }
+                let sum = String::from_utf8(vec![next(stream), next(stream)]).unwrap();
+                assert_eq!(u8::from_str_radix(&sum, 16), Ok(checksum(&data)), "{data}");
+                data
+            };
+
+            assert_eq!(exchange(&mut stream, "?"), "S05");
+            assert_eq!(exchange(&mut stream, "m200,2"), "6007");
+
+            //A corrupt packet is NAKed and nothing runs
+            stream.write_all(b"$s#00").unwrap();
+            let mut nak = [0u8];
+            stream.read_exact(&mut nak).unwrap();
+            assert_eq!(&nak, b"-");
+            assert_eq!(exchange(&mut stream, "p11"), "0002");
+
+            //Kill gets an ack, then the stub hangs up without a reply
+            write_packet(&mut stream, "k").unwrap();
+            let mut rest = Vec::new();
+            stream.read_to_end(&mut rest).unwrap();
+            assert_eq!(rest, b"+");
+        });
+
+        stub.accept(&listener).unwrap();
+        client.join().unwrap();
+        assert_eq!(stub.c8.pc, 0x200);
+    }
+}
\ No newline at end of file
@@ -1 +1,7 @@
-pub mod chip8;
\ No newline at end of file
+pub mod chip8;
+pub mod debug;
+pub mod errors;
+pub mod gdb;
+pub mod trace;
+
+pub use errors::{Error, Result, RomError, StateError};
\ No newline at end of file
@@ -0,0 +1,149 @@
+//Per-instruction trace logs.
+//
+//Each executed instruction that passes the filter is one line, with fixed-width fields
+//separated by single spaces so two traces can be compared with diff:
+//
+//    00000002 0204 F055     LD [I], V0               V:42 00 00 00 00 00 00 00 00 00 00 00 00 00 00 00 I:0301 DT:00 ST:00
+//
+//- CYCLE: 8 decimal digits, the number of steps before this one, counting filtered out ones
+//- PC: 4 hex digits, where the instruction was fetched from
+//- OPCODE: the raw instruction bytes in hex, 4 digits or 8 for F000 nnnn, padded to 8
+//- DISASSEMBLY: Cowgod syntax, padded to 24
+//- the registers, I and the timers as hex, after the instruction ran
+//
+//Lines end with a single \n. Nothing else is written, so a trace is just these lines.
+
+use std::convert::Infallible;
+use std::io::{self, Write};
+use std::ops::RangeInclusive;
+
+use chip8_decode::instructions::Instr;
+use chip8_decode::syntax::Mnemonic;
+
+use crate::chip8::{keyboard::Key, Chip8, FrameStep};
+use crate::Result;
+
+/// Which instructions a `Tracer` writes. The default traces everything.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TraceFilter {
+    /// Only instructions fetched from these addresses
+    pub range: Option<RangeInclusive<u16>>,
+    /// Only instructions with these mnemonics, as printed in the trace. `LD` matches
+    /// all of its forms. Empty means any.
+    pub kinds: Vec<Mnemonic>,
+}
+
+impl TraceFilter {
+    pub fn matches(&self, pc: u16, instr: &Instr) -> bool {
+        self.range.iter().all(|range| range.contains(&pc))
+            && (self.kinds.is_empty() || self.kinds.contains(&instr.mnemonic()))
+    }
+}
+
+/// Writes a trace line for each instruction it steps. Write errors don't interrupt
+/// execution: the first one is kept and returned by `finish`.
+pub struct Tracer<W: Write> {
+    out: W,
+    filter: TraceFilter,
+    cycle: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> Tracer<W> {
+    pub fn new(out: W, filter: TraceFilter) -> Self {
+        Self { out, filter, cycle: 0, error: None }
+    }
+
+    /// `Chip8::step`, then trace the instruction
+    pub fn step(&mut self, c8: &mut Chip8, next_key: Option<Key>) -> Result<Instr> {
+        let pc = c8.pc;
+        let instr = c8.step(next_key)?;
+        self.trace(c8, pc, &instr);
+        Ok(instr)
+    }
+
+    /// `Chip8::run_frame`, tracing every instruction
+    pub fn run_frame(&mut self, c8: &mut Chip8, next_key: Option<Key>) -> Result<Option<Instr>> {
+        let mut last = None;
+        c8.run_frame_with(next_key, |c8| {
+            let instr = self.step(c8, next_key)?;
+            last = Some(instr);
+            Ok(FrameStep::<Infallible>::Ran(instr))
+        })?;
+        Ok(last)
+    }
+
+    /// Trace an instruction the caller already executed from `pc`
+    pub fn trace(&mut self, c8: &Chip8, pc: u16, instr: &Instr) {
+        let cycle = self.cycle;
+        self.cycle += 1;
+        if self.error.is_some() || !self.filter.matches(pc, instr) {
+            return;
+        }
+
+        if let Err(e) = writeln!(self.out, "{}", line(cycle, pc, instr, c8)) {
+            self.error = Some(e);
+        }
+    }
+
+    /// Flush buffered lines, e.g. once per frame so a killed run still leaves a trace
+    pub fn flush(&mut self) {
+        if self.error.is_none() {
+            self.error = self.out.flush().err();
+        }
+    }
+
+    /// Flush the output and return it, or the first write error
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(e) = self.error {
+            return Err(e);
+        }
+        self.out.flush()?;
+        Ok(self.out)
+    }
+}
+
+/// One trace line, without the newline
+pub fn line(cycle: u64, pc: u16, instr: &Instr, c8: &Chip8) -> String {
+    let opcode: String = instr.to_bytes().iter().map(|byte| format!("{byte:02X}")).collect();
+    let regs: Vec<String> = c8.gpregs.iter().map(|reg| format!("{reg:02X}")).collect();
+    format!(
+        "{cycle:08} {pc:04X} {opcode:<8} {:<24} V:{} I:{:04X} DT:{:02X} ST:{:02X}",
+        instr.to_string(), regs.join(" "), c8.i_reg, c8.timers.delay(), c8.timers.sound(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chip8::Platform;
+
+    #[test]
+    fn trace() {
+        //LD I, 0x300; LD V0, 0x42; LD [I], V0; JP 0x206
+        let rom = [0xA3, 0x00, 0x60, 0x42, 0xF0, 0x55, 0x12, 0x06];
+        let mut c8 = Chip8::load_rom(Platform::Modern, &rom).unwrap();
+        let filter = TraceFilter { range: Some(0x202..=0x205), kinds: vec![] };
+        let mut tracer = Tracer::new(Vec::new(), filter);
+        for _ in 0..4 {
+            tracer.step(&mut c8, None).unwrap();
+        }
+
+        let out = String::from_utf8(tracer.finish().unwrap()).unwrap();
+        let regs = " 00".repeat(15);
+        assert_eq!(out, format!(
+            "00000001 0202 6042     LD V0, 0x42              V:42{regs} I:0300 DT:00 ST:00\n\
+             00000002 0204 F055     LD [I], V0               V:42{regs} I:0301 DT:00 ST:00\n"
+        ));
+    }
+
+    #[test]
+    fn kinds() {
+        let filter = TraceFilter { range: None, kinds: vec![Mnemonic::LD, Mnemonic::DRW] };
+        //LD V0, 0x42; LD [I], V0; DRW V0, V1, 5; ADD V0, 0x01
+        let matched: Vec<bool> = [0x6042, 0xF055, 0xD015, 0x7001]
+            .map(|opcode| filter.matches(0x200, &Instr::decode(opcode).unwrap()))
+            .into();
+        assert_eq!(matched, [true, true, true, false]);
+    }
+}
\ No newline at end of file
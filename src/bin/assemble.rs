@@ -0,0 +1,48 @@
+use std::path::Path;
+
+use chip8_decode::asm::assemble;
+use chip8_decode::octo::{self, Symbol};
+
+//Usage: assemble <source> [output] [--symbols]
+//Sources ending in .8o are compiled as Octo, anything else is Cowgod-style assembly.
+//Writes <source>.c8 when no output is given, and --symbols prints the symbol table.
+fn main() {
+    let args: Vec<String> = std::env::args().skip(1).filter(|arg| !arg.starts_with("--")).collect();
+    let path = args.first().cloned().unwrap_or_else(|| panic!("Usage: assemble <source> [output] [--symbols]"));
+    let out_path = args.get(1).cloned().unwrap_or_else(|| Path::new(&path).with_extension("c8").display().to_string());
+    let src = std::fs::read_to_string(&path).unwrap_or_else(|_| panic!("Failed to open file \"{path}\" (does it exist?)"));
+
+    let result = if path.ends_with(".8o") {
+        octo::compile(&src).map(|program| {
+            let symbols = program.symbols.into_iter()
+                .map(|(name, symbol)| match symbol {
+                    Symbol::Label(addr) => format!("{name} = 0x{addr:03X}"),
+                    Symbol::Const(value) => format!("{name} = {value}"),
+                    Symbol::Alias(reg) => format!("{name} = {reg:?}"),
+                })
+                .collect();
+            (program.bytes, symbols)
+        })
+    } else {
+        assemble(&src).map(|program| {
+            let symbols = program.labels.into_iter()
+                .map(|(name, addr)| format!("{name} = 0x{addr:03X}"))
+                .collect::<Vec<_>>();
+            (program.bytes, symbols)
+        })
+    };
+
+    match result {
+        Ok((bytes, symbols)) => {
+            std::fs::write(&out_path, &bytes).unwrap_or_else(|e| panic!("Failed to write \"{out_path}\": {e}"));
+            println!("Wrote {} bytes to {out_path}", bytes.len());
+            if std::env::args().any(|arg| arg == "--symbols") {
+                symbols.iter().for_each(|line| println!("{line}"));
+            }
+        },
+        Err(e) => {
+            eprintln!("{path}:{e}");
+            std::process::exit(1);
+        },
+    }
+}
\ No newline at end of file
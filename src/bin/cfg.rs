@@ -0,0 +1,18 @@
+use chip8::cli::{flag, read_rom, rom_path};
+use chip8_decode::cfg::Cfg;
+use chip8_decode::disasm::Disassembly;
+
+//Usage: cfg [rom] [--format=dot|json]
+//Prints the control flow graph of the ROM, as Graphviz DOT by default.
+fn main() {
+    let path = rom_path();
+    let bytes = read_rom(&path);
+    let format = flag("--format=").unwrap_or("dot".into());
+
+    let cfg = Cfg::new(&Disassembly::new(&bytes));
+    match format.as_ref() {
+        "dot" => print!("{}", cfg.to_dot()),
+        "json" => println!("{}", cfg.to_json()),
+        _ => panic!("Unknown format \"{format}\", expected dot or json"),
+    }
+}
\ No newline at end of file
@@ -1,6 +1,6 @@
-use chip8_hw::chip8::{Chip8, QUIRKS_NEW};
+use chip8::cli::load_rom;
 
 fn main() {
-    let c8 = Chip8::load_rom(QUIRKS_NEW, &[]);
+    let c8 = load_rom(&[]);
     println!("{c8:#X?}");
 }
\ No newline at end of file
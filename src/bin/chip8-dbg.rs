@@ -0,0 +1,18 @@
+use chip8::cli::{load_rom, read_rom, rom_path, syntax};
+use chip8::session::Session;
+use chip8_decode::syntax::Formatter;
+
+//Usage: chip8-dbg [rom] [--platform=<name>] [--syntax=cowgod|octo]
+//
+//Reads gdb-style commands from stdin, one per line. An empty line repeats the last command.
+//See chip8::session::HELP, or type help, for the commands.
+fn main() {
+    let path = rom_path();
+    let bytes = read_rom(&path);
+    let mut session = Session::new(load_rom(&bytes), Formatter::new(syntax()));
+
+    let stdout = std::io::stdout();
+    if let Err(e) = session.serve(std::io::stdin().lock(), &mut stdout.lock()) {
+        eprintln!("Debugger I/O failed: {e}.");
+    }
+}
\ No newline at end of file
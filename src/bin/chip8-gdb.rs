@@ -0,0 +1,18 @@
+use chip8::cli::{flag, load_rom, read_rom, rom_path};
+use chip8_hw::gdb::GdbStub;
+
+//Usage: chip8-gdb [rom] [--port=1234] [--platform=<name>]
+//
+//Waits for a GDB Remote Serial Protocol frontend on localhost, e.g. `target remote :1234`
+fn main() {
+    let path = rom_path();
+    let bytes = read_rom(&path);
+
+    let port = flag("--port=").map_or(1234, |port| port.parse().unwrap_or_else(|e| panic!("Bad port {port}: {e}")));
+
+    let mut stub = GdbStub::new(load_rom(&bytes));
+    println!("Waiting for a debugger on 127.0.0.1:{port}.");
+    if let Err(e) = stub.listen(("127.0.0.1", port)) {
+        eprintln!("Debugger connection failed: {e}.");
+    }
+}
\ No newline at end of file
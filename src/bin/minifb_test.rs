@@ -1,8 +1,9 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Write;
+use chip8::cli::{load_rom, positional_args, read_rom, rom_path, syntax, tracer};
 use chip8_decode::instructions::Instr;
+use chip8_decode::syntax::Formatter;
 use chip8_hw::chip8::keyboard::Key;
-use chip8_hw::chip8::{Chip8, QUIRKS_NEW, STACK_LIMIT, VRAM_HEIGHT, VRAM_WH, VRAM_WIDTH};
+use chip8_hw::chip8::{Chip8, WallClock, HIRES_WH, STACK_LIMIT, VRAM_HEIGHT, VRAM_WIDTH};
 use minifb::{Key as FBKey, KeyRepeat, Window, WindowOptions};
 
 static KEY_MAP: &[(FBKey, Key)] = &[
@@ -24,19 +25,47 @@ static KEY_MAP: &[(FBKey, Key)] = &[
     (FBKey::V   , Key::KF),
 ];
 
+//Hold to run backwards, one frame per frame
+const REWIND_KEY: FBKey = FBKey::Backspace;
+//How far back REWIND_KEY can go, 10 seconds
+const REWIND_FRAMES: usize = 600;
+
+//(save, load) hotkeys for each savestate slot
+static SLOT_KEYS: &[(FBKey, FBKey)] = &[
+    (FBKey::F1, FBKey::F5),
+    (FBKey::F2, FBKey::F6),
+    (FBKey::F3, FBKey::F7),
+    (FBKey::F4, FBKey::F8),
+];
+
+//Colors for pixels lit on XO-CHIP planes other than just the first,
+//indexed by the plane bitmask - 2
+static PLANE_COLORS: [u32; 14] = [
+    0x00FF6600, 0x00662200, 0x00FF0000, 0x00AA0000, 0x00FF00FF, 0x00AA00AA, 0x000000FF,
+    0x000000AA, 0x0000FFFF, 0x0000AAAA, 0x0000FF00, 0x0000AA00, 0x00AAAAAA, 0x00FFFFFF,
+];
+
 struct Scheme {
     fg: u32,
     bg: u32,
 }
 
 impl Scheme {
+    fn color(&self, planes: u8) -> u32 {
+        match planes {
+            0 => self.bg,
+            1 => self.fg,
+            n => PLANE_COLORS[n as usize - 2],
+        }
+    }
+
     fn from_env() -> Scheme {
         let default = Scheme {
             fg: 0x00FFAA00,
             bg: 0,
         };
 
-        if let Some(arg) = std::env::args().skip(2).next() {
+        if let Some(arg) = positional_args().get(1) {
             return match arg.to_lowercase().as_ref() {
                 "light" => Scheme {
                     fg: 0,
@@ -52,13 +81,14 @@ impl Scheme {
 
 fn main() {
     let (rom_name, mut c8) = chip8();
+    let mut tracer = tracer();
     let scheme = Scheme::from_env();
     let (active, halted) = (format!("chip8 - {rom_name}"), format!("<HALTED> - chip8 - {rom_name}"));
 
     let stdout = std::io::stdout();
     let mut out = stdout.lock();
 
-    let mut display_buf: Vec<u32> = vec![0; VRAM_WH];
+    let mut display_buf: Vec<u32> = vec![0; HIRES_WH];
     let mut display = Window::new(
         &active,
         VRAM_WIDTH,
@@ -69,32 +99,61 @@ fn main() {
             ..Default::default()
         },
     ).expect("Failed to create c8 display window.");
+    //One loop iteration is one 60 Hz frame
+    display.limit_update_rate(Some(WallClock::FRAME));
 
     //Clear the terminal for the debug console output
     print!("{esc}[2J", esc = 27 as char);
     let mut do_one_step = false;
+    //The PC has already moved on by the time an instruction is printed, so leave out the address column
+    let formatter = Formatter { addresses: false, ..Formatter::new(syntax()) };
 
     while display.is_open() && !display.is_key_down(FBKey::Escape) {
         update_key_states(&mut c8, &display);
         display.set_title(if c8.is_halted() { &halted } else { &active });
 
-        if !c8.is_halted() {
-            match c8.step(next_key(&display)) {
+        if display.is_key_down(REWIND_KEY) {
+            //Rewinding doesn't pause or unpause
+            let halted = c8.is_halted();
+            c8.rewind(1);
+            c8.set_halted(halted);
+        } else if !c8.is_halted() {
+            let result = match (tracer.as_mut(), do_one_step) {
+                (Some(tracer), true) => tracer.step(&mut c8, next_key(&display)).map(Some),
+                (Some(tracer), false) => tracer.run_frame(&mut c8, next_key(&display)),
+                (None, true) => c8.step(next_key(&display)).map(Some),
+                (None, false) => c8.run_frame(next_key(&display)),
+            };
+            if let Some(tracer) = tracer.as_mut() {
+                tracer.flush();
+            }
+
+            match result {
                 Err(e) => {
                     let _ = writeln!(out, "Execution halted: {e}.");
                     c8.set_halted(true);
                 }
-                Ok(ins) => print_env(&mut out, &c8, ins),
+                Ok(Some(ins)) => print_env(&mut out, &c8, &formatter, ins),
+                Ok(None) => {},
             }
-
-            display_buf.iter_mut()
-                .enumerate()
-                .for_each(|(idx, pix)| *pix = if c8.pixel_on(idx) { scheme.fg } else { scheme.bg });
         }
 
-        display
-            .update_with_buffer(&display_buf, VRAM_WIDTH, VRAM_HEIGHT)
-            .expect("Failed to update display buffer on window.");
+        if c8.display.take_changed() {
+            let (width, height) = (c8.display.width(), c8.display.height());
+            for y in c8.display.dirty_rows() {
+                let row = &mut display_buf[y * width..(y + 1) * width];
+                row.iter_mut()
+                    .zip(c8.display.row(y))
+                    .for_each(|(pix, &planes)| *pix = scheme.color(planes));
+            }
+            c8.display.clear_dirty();
+
+            display
+                .update_with_buffer(&display_buf, width, height)
+                .expect("Failed to update display buffer on window.");
+        } else {
+            display.update();
+        }
         
         if do_one_step {
             c8.set_halted(true);
@@ -107,24 +166,49 @@ fn main() {
             c8.set_halted(false);
             do_one_step = true;
         }
+
+        handle_savestates(&mut out, &mut c8, &display, &rom_name);
+    }
+}
+
+//F1-F4 save to slots 1-4 next to the ROM, F5-F8 load them back
+fn handle_savestates(out: &mut impl Write, c8: &mut Chip8, window: &Window, rom_name: &str) {
+    for (slot, (save, load)) in SLOT_KEYS.iter().enumerate() {
+        let path = format!("{rom_name}.{}.state", slot + 1);
+
+        if window.is_key_pressed(*save, KeyRepeat::No) {
+            match std::fs::write(&path, c8.save_state()) {
+                Ok(_) => { let _ = writeln!(out, "Saved state to {path}."); },
+                Err(e) => { let _ = writeln!(out, "Failed to save state to {path}: {e}."); },
+            }
+        } else if window.is_key_pressed(*load, KeyRepeat::No) {
+            let state = std::fs::read(&path).map_err(|e| e.to_string())
+                .and_then(|bytes| Chip8::load_state(&bytes).map_err(|e| e.to_string()));
+            match state {
+                Ok(state) => {
+                    *c8 = state;
+                    c8.enable_rewind(REWIND_FRAMES);
+                },
+                Err(e) => { let _ = writeln!(out, "Failed to load state from {path}: {e}."); },
+            }
+        }
     }
 }
 
 fn chip8() -> (String, Chip8) {
-    let path = std::env::args().nth(1).unwrap_or("rom.c8".into());
-    let file = File::open(&path).expect(&format!("Failed to open file \"{path}\" (does it exist?)"));
-    let bytes: Vec<u8> = file.bytes()
-        .filter_map(|byte| byte.ok())
-        .collect();
-    
-    (path, Chip8::load_rom(QUIRKS_NEW, &bytes))
+    let path = rom_path();
+    let bytes = read_rom(&path);
+
+    let mut c8 = load_rom(&bytes);
+    c8.enable_rewind(REWIND_FRAMES);
+    (path, c8)
 }
 
 //If any key was released, return it (LDKB)
 //Otherwise, none. When LDKB checks this,
 //if it's none, the emulator will loop back to
-//the instruction. ST and DT are not updated,
-//faking "halting" the emulator until a key is ready.
+//the instruction, faking "halting" the emulator
+//until a key is ready. ST and DT keep counting down.
 fn next_key(window: &Window) -> Option<Key> {
     KEY_MAP.iter()
         .find(|(fbkey, _)| window.is_key_released(*fbkey))
@@ -145,7 +229,7 @@ fn update_key_states(c8: &mut Chip8, window: &Window) {
 }
 
 #[allow(unused_must_use)]
-fn print_env(out: &mut impl Write, c8: &Chip8, ins: Instr) {
+fn print_env(out: &mut impl Write, c8: &Chip8, formatter: &Formatter, ins: Instr) {
     //https://stackoverflow.com/a/34837038
     #[inline(always)]
     fn move_to(out: &mut impl Write, x: usize, y: usize) {
@@ -156,12 +240,12 @@ fn print_env(out: &mut impl Write, c8: &Chip8, ins: Instr) {
     buf.push_str("REGS:\n");
     for reg in 0..c8.gpregs.len() {
         if reg > 0 && reg % 4 == 0 {
-            buf.push_str("\n");
+            buf.push('\n');
         }
         buf.push_str(&format!("V{:X} = 0x{:02X}  ", reg, c8.gpregs[reg]));
     }
-    buf.push_str("\n");
-    buf.push_str(&format!(" I = 0x{:04X}\n\n", *c8.i_reg));
+    buf.push('\n');
+    buf.push_str(&format!(" I = 0x{:04X}\n\n", c8.i_reg));
 
     let kb = &c8.keyboard;
     let st = |b| if b { "*" } else { " " };
@@ -175,7 +259,7 @@ fn print_env(out: &mut impl Write, c8: &Chip8, ins: Instr) {
     //the extra spaces overwrite artifacts from the previous instruction
     //do not remove. Field width on the instruction puts weird spaces in the
     //structure.
-    buf.push_str(&format!("PC:\n0x{:04X} -> {ins:?}                                 ", c8.pc));
+    buf.push_str(&format!("PC:\n0x{:04X} -> {}                                 ", c8.pc, formatter.instr(c8.pc, &ins)));
 
     const SP_X: usize = 46;
     move_to(out, SP_X, 0);
@@ -184,7 +268,7 @@ fn print_env(out: &mut impl Write, c8: &Chip8, ins: Instr) {
         move_to(out, SP_X, STACK_LIMIT - i + 1);
         write!(out, "{:2}: 0x{:04X}", i, c8.stack[i]);
 
-        if c8.sp as usize == i {
+        if c8.sp == i {
             write!(out, " <- SP");
         } else {
             write!(out, "      ");
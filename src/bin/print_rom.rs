@@ -1,19 +1,13 @@
-use std::fs::File;
-use std::io::Read;
-
-use chip8_decode::instructions::Instr;
+use chip8::cli::{read_rom, rom_path, syntax};
+use chip8_decode::disasm::Disassembly;
+use chip8_decode::syntax::Formatter;
 
+//Usage: print_rom [rom] [--syntax=cowgod|octo]
+//Octo source (.8o) is compiled first, so the listing shows what it assembles to.
 fn main() {
-    let path = std::env::args().nth(1).unwrap_or("rom.c8".into());
-    let file = File::open(&path).expect(&format!("Failed to open file \"{path}\" (does it exist?)"));
-    let bytes: Vec<u8> = file.bytes()
-        .filter_map(|byte| byte.ok())
-        .collect();
-    
-    dbg!(&bytes);
+    let path = rom_path();
+    let bytes = read_rom(&path);
 
-    bytes.chunks(2)
-        .map(|bytes: &[u8]| (bytes[0] as u16) << 8 | bytes[1] as u16)
-        .map(Instr::decode)
-        .for_each(|instr| { println!("{instr:?}"); });
+    let dis = Disassembly::new(&bytes);
+    print!("{}", dis.listing(&Formatter::new(syntax())));
 }
\ No newline at end of file
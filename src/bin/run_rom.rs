@@ -1,55 +1,66 @@
-use std::fs::File;
-use std::io::{Read, Write};
+use std::io::Write;
 use std::time::Duration;
 
-use chip8_hw::chip8::{Chip8, QUIRKS_NEW, VRAM_HEIGHT, VRAM_WIDTH};
+use chip8::cli::{load_rom, read_rom, rom_path, tracer};
+use chip8_hw::chip8::{Display, WallClock};
+use chip8_hw::trace::Tracer;
 
 fn main() {
-    let path = std::env::args().nth(1).unwrap_or("rom.c8".into());
-    let file = File::open(&path).expect(&format!("Failed to open file \"{path}\" (does it exist?)"));
-    let bytes: Vec<u8> = file.bytes()
-        .filter_map(|byte| byte.ok())
-        .collect();
+    let path = rom_path();
+    let bytes = read_rom(&path);
     
-    let mut c8 = Chip8::load_rom(QUIRKS_NEW, &bytes);
+    let mut c8 = load_rom(&bytes);
+    let mut tracer = tracer();
 
     let stdout = std::io::stdout();
     let mut out = stdout.lock();
 
-    loop {
-        let result = c8.step(None);
+    let mut clock = WallClock::default();
+    'run: loop {
+        for _ in 0..clock.frames_due() {
+            let result = match tracer.as_mut() {
+                Some(tracer) => tracer.run_frame(&mut c8, None),
+                None => c8.run_frame(None),
+            };
 
-        match result {
-            // Ok(instr) => println!("{instr:?}"),
-            Err(e) => {
+            // if let Ok(Some(instr)) = result { println!("{instr:?}") }
+            if let Err(e) = result {
                 dbg!(e);
-                break;
+                break 'run;
             }
-            _ => {},
+        }
+        if let Some(tracer) = tracer.as_mut() {
+            tracer.flush();
         }
 
         // println!("regs = {:04X?}, pc = {:4X}, sp = {:04X}, stack = {:04X?}", c8.gpregs, c8.pc, c8.sp, c8.stack);
-        cls(&mut out);
-        display(&mut out, &c8.vram);
-        std::thread::sleep(Duration::from_millis(10));
+        if c8.display.take_changed() {
+            cls(&mut out);
+            display(&mut out, &c8.display);
+        }
+        std::thread::sleep(Duration::from_millis(1));
     }
     println!("Execution halted.");
+
+    if let Some(Err(e)) = tracer.map(Tracer::finish) {
+        eprintln!("Failed to write the trace: {e}.");
+    }
 }
 
 fn cls(out: &mut impl Write) {
     let _ = write!(out, "{0}[2J{0}[1;1H", 27 as char);
 }
 
-fn display(out: &mut impl Write, vram: &[bool]) {
-    for y in 0 .. VRAM_HEIGHT {
-        for x in 0 .. VRAM_WIDTH {
-            let _ = if vram[y * VRAM_WIDTH + x] {
+fn display(out: &mut impl Write, display: &Display) {
+    for y in 0 .. display.height() {
+        for x in 0 .. display.width() {
+            let _ = if display.pixel_on(x, y) {
                 write!(out, "#")
             } else {
                 write!(out, " ")
             };
         }
-        let _ = writeln!(out, "");
+        let _ = writeln!(out);
     }
 
     let _ = out.flush();
@@ -0,0 +1,89 @@
+//Command line flags shared by the binaries. Flags are --name=value and may appear
+//anywhere, everything else is a positional argument. Bad values panic with a message.
+
+use std::fs::File;
+use std::io::BufWriter;
+
+use chip8_decode::octo;
+use chip8_decode::syntax::Syntax;
+use chip8_hw::chip8::{Chip8, Platform};
+use chip8_hw::trace::{TraceFilter, Tracer};
+
+/// The value of the first argument starting with `prefix`, e.g. `--port=`
+pub fn flag(prefix: &str) -> Option<String> {
+    std::env::args().find_map(|arg| arg.strip_prefix(prefix).map(str::to_owned))
+}
+
+/// Command line arguments, without the program name or any --flags
+pub fn positional_args() -> Vec<String> {
+    std::env::args()
+        .skip(1)
+        .filter(|arg| !arg.starts_with("--"))
+        .collect()
+}
+
+/// The first positional argument, defaulting to rom.c8
+pub fn rom_path() -> String {
+    positional_args().into_iter().next().unwrap_or("rom.c8".into())
+}
+
+/// Read the ROM at `path`. Octo source (.8o) is compiled on the fly.
+pub fn read_rom(path: &str) -> Vec<u8> {
+    let bytes = std::fs::read(path).unwrap_or_else(|_| panic!("Failed to open file \"{path}\" (does it exist?)"));
+    if !path.ends_with(".8o") {
+        return bytes;
+    }
+    let src = String::from_utf8_lossy(&bytes);
+    octo::compile(&src).unwrap_or_else(|e| panic!("{path}:{e}")).bytes
+}
+
+/// A machine for --platform running `rom`, with RND seeded by --seed
+pub fn load_rom(rom: &[u8]) -> Chip8 {
+    let c8 = match seed() {
+        Some(seed) => Chip8::load_rom_seeded(platform(), rom, seed),
+        None => Chip8::load_rom(platform(), rom),
+    };
+    c8.unwrap_or_else(|e| panic!("{e}"))
+}
+
+/// Pick the platform with --platform=<name>, defaulting to modern CHIP-8
+pub fn platform() -> Platform {
+    flag("--platform=")
+        .map(|name| name.parse().unwrap_or_else(|e| panic!("{e}")))
+        .unwrap_or_default()
+}
+
+/// Seed RND with --seed=<n> so runs can be replayed, or from entropy without it
+pub fn seed() -> Option<u64> {
+    flag("--seed=").map(|seed| seed.parse().unwrap_or_else(|e| panic!("Bad seed {seed}: {e}")))
+}
+
+/// Pick the disassembly syntax with --syntax=<name>, defaulting to Cowgod
+pub fn syntax() -> Syntax {
+    flag("--syntax=")
+        .map(|name| name.parse().unwrap_or_else(|e| panic!("{e}")))
+        .unwrap_or_default()
+}
+
+/// Trace to a file with --trace=<path>, filtered by --trace-range=<start>-<end> (hex)
+/// and --trace-instr=<MNEMONIC>,<MNEMONIC>. See chip8_hw::trace for the line format.
+///
+/// Mnemonics are the first word of the Cowgod disassembly whatever --syntax says, the
+/// same ones chip8-dbg's break takes. They match every form of the instruction, so LD
+/// traces LD Vx, kk along with LD I, nnn, LD [I], Vx and the rest; narrow it down
+/// with --trace-range or by grepping the trace.
+pub fn tracer() -> Option<Tracer<BufWriter<File>>> {
+    let path = flag("--trace=")?;
+    let file = File::create(&path).unwrap_or_else(|e| panic!("Failed to create trace file \"{path}\": {e}"));
+
+    let hex = |addr: &str| u16::from_str_radix(addr.trim_start_matches("0x"), 16).unwrap_or_else(|e| panic!("Bad trace address {addr}: {e}"));
+    let range = flag("--trace-range=").map(|range| {
+        let (start, end) = range.split_once('-').unwrap_or_else(|| panic!("Expected --trace-range=<start>-<end>, found {range}"));
+        hex(start)..=hex(end)
+    });
+    let kinds = flag("--trace-instr=")
+        .map(|kinds| kinds.split(',').map(|kind| kind.parse().unwrap_or_else(|e| panic!("{e}"))).collect())
+        .unwrap_or_default();
+
+    Some(Tracer::new(BufWriter::new(file), TraceFilter { range, kinds }))
+}
\ No newline at end of file
@@ -0,0 +1,4 @@
+//Code shared by the binaries in src/bin.
+
+pub mod cli;
+pub mod session;
\ No newline at end of file
@@ -0,0 +1,415 @@
+//A gdb-style command line around `Debugger`, as used by chip8-dbg.
+//
+//Commands are read one per line and an empty line repeats the last one. Running commands
+//go through `Debugger::run`, so the timers tick once per frame's worth of instructions
+//however the run is split up.
+
+use std::io::{self, BufRead, Write};
+use std::ops::RangeInclusive;
+
+use chip8_decode::instructions::Instr;
+use chip8_decode::syntax::Formatter;
+
+use chip8_hw::chip8::Chip8;
+use chip8_hw::debug::{Breakpoint, Debugger, Event, Hit, Register};
+
+pub static HELP: &str = "\
+break [addr|OP|Vx|I]      stop at an address, before every instruction disas prints as OP (e.g.
+                          DRW, or LD for all its forms), or when a register changes. Without an argument, list breakpoints.
+watch addr[-end]          stop after a write to memory
+rwatch addr[-end]         stop after a read from memory
+delete [id]               delete a breakpoint, or all of them
+step [n]                  execute n instructions (default 1)
+next                      like step, but runs a CALL until it returns
+finish                    run until the current subroutine returns
+continue [frames]         run until a breakpoint, the machine halts or `frames` frames have passed
+regs                      print the registers
+x/n addr                  print n bytes of memory (default 16)
+set Vx|I|PC = value       change a register
+disas [addr] [n]          disassemble n instructions (default 8) from addr (default PC)
+bt                        print the call stack
+quit";
+
+//How long a run without a limit may take before giving up, ten minutes of emulated time
+const MAX_FRAMES: usize = 60 * 60 * 10;
+
+pub struct Session {
+    pub c8: Chip8,
+    pub dbg: Debugger,
+    formatter: Formatter,
+}
+
+//Why a run stopped early
+enum Stop {
+    Break(Vec<Hit>),
+    Halted,
+    Error(chip8_hw::Error),
+}
+
+impl Session {
+    pub fn new(c8: Chip8, formatter: Formatter) -> Self {
+        Self { c8, dbg: Debugger::new(), formatter }
+    }
+
+    /// Execute commands from `input` until it ends or a command quits. Errors in a
+    /// command are reported to `out` and don't end the session.
+    pub fn serve(&mut self, input: impl BufRead, out: &mut impl Write) -> io::Result<()> {
+        let mut last = String::new();
+        for line in input.lines() {
+            let line = line?;
+            if !line.trim().is_empty() {
+                last = line;
+            }
+
+            match self.execute(out, &last) {
+                Ok(true) => {},
+                Ok(false) => break,
+                Err(e) => writeln!(out, "{e}.")?,
+            }
+            out.flush()?;
+        }
+        Ok(())
+    }
+
+    //Execute one instruction through the debugger
+    fn step(&mut self) -> Option<Stop> {
+        if self.c8.is_halted() {
+            return Some(Stop::Halted);
+        }
+        match self.dbg.run(&mut self.c8, None, 1) {
+            Ok(hits) => hits.map(Stop::Break),
+            Err(e) => Some(Stop::Error(e)),
+        }
+    }
+
+    //Step until `done` holds after an instruction, or at most `limit` instructions
+    fn run_until(&mut self, limit: usize, mut done: impl FnMut(&Chip8) -> bool) -> Option<Stop> {
+        for _ in 0..limit {
+            if let Some(stop) = self.step() {
+                return Some(stop);
+            }
+            if done(&self.c8) {
+                break;
+            }
+        }
+        None
+    }
+
+    fn report(&self, out: &mut impl Write, stop: Option<Stop>) {
+        match stop {
+            Some(Stop::Break(hits)) => {
+                for hit in hits {
+                    let _ = writeln!(out, "{}", describe(&hit));
+                }
+            },
+            Some(Stop::Halted) => { let _ = writeln!(out, "The machine is halted."); },
+            Some(Stop::Error(e)) => { let _ = writeln!(out, "Execution stopped: {e}."); },
+            None => {},
+        }
+        self.disas(out, self.c8.pc, 1);
+    }
+
+    fn disas(&self, out: &mut impl Write, mut addr: u16, count: usize) {
+        for _ in 0..count {
+            let marker = if addr == self.c8.pc { "=>" } else { "  " };
+            let (len, text) = match self.decode(addr) {
+                Some(instr) => (instr.byte_len(), self.formatter.instr(addr, &instr)),
+                None => {
+                    let bytes: Vec<u8> = (0..2).map(|offset| self.byte(addr.wrapping_add(offset))).collect();
+                    (2, self.formatter.data(addr, &bytes))
+                },
+            };
+            let _ = writeln!(out, "{marker} {text}");
+            addr = addr.wrapping_add(len);
+        }
+    }
+
+    fn decode(&self, addr: u16) -> Option<Instr> {
+        let word = |addr: u16| (self.byte(addr) as u16) << 8 | self.byte(addr.wrapping_add(1)) as u16;
+        let opcode = word(addr);
+        let options = self.c8.decode_options();
+        if self.c8.instr_set().has_xochip() && opcode == 0xF000 {
+            Instr::decode_long_with(opcode, word(addr.wrapping_add(2)), &options).ok()
+        } else {
+            Instr::decode_with(opcode, &options).ok()
+        }
+    }
+
+    fn byte(&self, addr: u16) -> u8 {
+        self.c8.ram[addr as usize % self.c8.ram.len()]
+    }
+
+    fn regs(&self, out: &mut impl Write) {
+        for row in self.c8.gpregs.chunks(4).enumerate() {
+            let regs: Vec<String> = row.1.iter().enumerate()
+                .map(|(idx, value)| format!("V{:X} = 0x{value:02X}", row.0 * 4 + idx))
+                .collect();
+            let _ = writeln!(out, "{}", regs.join("  "));
+        }
+        let _ = writeln!(out, " I = 0x{:04X}  PC = 0x{:04X}  SP = {}", self.c8.i_reg, self.c8.pc, self.c8.sp);
+        let _ = writeln!(out, "DT = 0x{:02X}  ST = 0x{:02X}", self.c8.timers.delay(), self.c8.timers.sound());
+    }
+
+    fn examine(&self, out: &mut impl Write, addr: u16, count: usize) {
+        for row in 0..count.div_ceil(8) {
+            let start = addr.wrapping_add(row as u16 * 8);
+            let bytes: Vec<String> = (0..(count - row * 8).min(8))
+                .map(|offset| format!("{:02X}", self.byte(start.wrapping_add(offset as u16))))
+                .collect();
+            let _ = writeln!(out, "0x{start:04X}:  {}", bytes.join(" "));
+        }
+    }
+
+    //Frame 0 is the current PC, the rest are the CALLs that are still waiting to return
+    fn backtrace(&self, out: &mut impl Write) {
+        let _ = writeln!(out, "#0  0x{:04X}", self.c8.pc);
+        for (frame, ret) in self.c8.stack[..self.c8.sp].iter().rev().enumerate() {
+            let _ = writeln!(out, "#{}  0x{:04X}  returns to 0x{ret:04X}", frame + 1, ret.wrapping_sub(2));
+        }
+    }
+
+    /// Execute one command line, writing its output to `out`. False if the command
+    /// ends the session, and an error message if the command is invalid.
+    pub fn execute(&mut self, out: &mut impl Write, line: &str) -> Result<bool, String> {
+        let mut words = line.split_whitespace();
+        let Some(command) = words.next() else {
+            return Ok(true);
+        };
+        let args: Vec<&str> = words.collect();
+
+        //gdb's x/n addr keeps the count in the command itself
+        if let Some(count) = command.strip_prefix("x/").or((command == "x").then_some("16")) {
+            let count = parse_num(count)?;
+            let addr = parse_addr(args.first().ok_or("x needs an address")?)?;
+            self.examine(out, addr, count);
+            return Ok(true);
+        }
+
+        match command {
+            "break" | "b" => match args.first() {
+                None => self.list(out),
+                Some(arg) => {
+                    let breakpoint = parse_breakpoint(arg)?;
+                    let _ = writeln!(out, "Breakpoint {}, {}", self.dbg.add(breakpoint.clone()), summary(&breakpoint));
+                },
+            },
+            "watch" | "rwatch" => {
+                let range = parse_range(args.first().ok_or("watch needs an address")?)?;
+                let watchpoint = if command == "watch" { Breakpoint::Write(range) } else { Breakpoint::Read(range) };
+                let _ = writeln!(out, "Watchpoint {}, {}", self.dbg.add(watchpoint.clone()), summary(&watchpoint));
+            },
+            "delete" | "d" => match args.first() {
+                None => self.dbg.clear(),
+                Some(id) => {
+                    let id = parse_num(id)?;
+                    self.dbg.remove(id).ok_or(format!("No breakpoint {id}"))?;
+                },
+            },
+            "step" | "s" => {
+                let count = args.first().map(|n| parse_num(n)).transpose()?.unwrap_or(1);
+                let stop = self.run_until(count, |_| false);
+                self.report(out, stop);
+            },
+            "next" | "n" => {
+                let stop = match self.decode(self.c8.pc) {
+                    Some(Instr::CALL(_)) => {
+                        let (ret, sp) = (self.c8.pc.wrapping_add(2), self.c8.sp);
+                        self.run_until(MAX_FRAMES * self.c8.instructions_per_frame(), |c8| c8.pc == ret && c8.sp == sp)
+                    },
+                    _ => self.run_until(1, |_| false),
+                };
+                self.report(out, stop);
+            },
+            "finish" => {
+                let sp = self.c8.sp;
+                if sp == 0 {
+                    return Err("Not in a subroutine".into());
+                }
+                let stop = self.run_until(MAX_FRAMES * self.c8.instructions_per_frame(), |c8| c8.sp < sp);
+                self.report(out, stop);
+            },
+            "continue" | "c" => {
+                let frames = args.first().map(|n| parse_num(n)).transpose()?.unwrap_or(MAX_FRAMES);
+                let mut stop = None;
+                for _ in 0..frames {
+                    if self.c8.is_halted() {
+                        stop = Some(Stop::Halted);
+                        break;
+                    }
+                    match self.dbg.run_frame(&mut self.c8, None) {
+                        Ok(None) => {},
+                        Ok(Some(hits)) => {
+                            stop = Some(Stop::Break(hits));
+                            break;
+                        },
+                        Err(e) => {
+                            stop = Some(Stop::Error(e));
+                            break;
+                        },
+                    }
+                }
+                self.report(out, stop);
+            },
+            "regs" => self.regs(out),
+            "set" => {
+                //set V3 = 0x10, the = is optional
+                let args: Vec<&str> = args.into_iter().filter(|arg| *arg != "=").collect();
+                let [reg, value] = args[..] else {
+                    return Err("Usage: set Vx|I|PC = value".into());
+                };
+                match (reg.to_uppercase().as_ref(), reg.parse()) {
+                    ("PC", _) => self.c8.pc = parse_addr(value)?,
+                    (_, Ok(Register::I)) => self.c8.i_reg = parse_addr(value)?,
+                    (_, Ok(Register::V(reg))) => {
+                        let value = parse_num(value)?;
+                        self.c8.gpregs[reg] = u8::try_from(value).map_err(|_| format!("{value} doesn't fit in {reg:?}"))?;
+                    },
+                    _ => return Err(format!("Unknown register {reg}")),
+                }
+            },
+            "disas" => {
+                let addr = args.first().map(|addr| parse_addr(addr)).transpose()?.unwrap_or(self.c8.pc);
+                let count = args.get(1).map(|n| parse_num(n)).transpose()?.unwrap_or(8);
+                self.disas(out, addr, count);
+            },
+            "bt" => self.backtrace(out),
+            "help" | "h" => { let _ = writeln!(out, "{HELP}"); },
+            "quit" | "q" => return Ok(false),
+            _ => return Err(format!("Unknown command \"{command}\", try help")),
+        }
+
+        Ok(true)
+    }
+
+    fn list(&self, out: &mut impl Write) {
+        for (id, breakpoint, enabled) in self.dbg.breakpoints() {
+            let _ = writeln!(out, "{id}  {}{}", summary(breakpoint), if enabled { "" } else { " (disabled)" });
+        }
+    }
+}
+
+fn summary(breakpoint: &Breakpoint) -> String {
+    match breakpoint {
+        Breakpoint::Exec(addr) => format!("at 0x{addr:04X}"),
+        Breakpoint::Read(range) => format!("read 0x{:04X}-0x{:04X}", range.start(), range.end()),
+        Breakpoint::Write(range) => format!("write 0x{:04X}-0x{:04X}", range.start(), range.end()),
+        Breakpoint::Register(reg) => format!("{reg} changes"),
+        Breakpoint::Instr(mnemonic) => format!("every {mnemonic}"),
+    }
+}
+
+fn describe(hit: &Hit) -> String {
+    let Hit { id, pc, event } = hit;
+    match event {
+        Event::Exec => format!("Breakpoint {id} at 0x{pc:04X}"),
+        Event::Instr(instr) => format!("Breakpoint {id}, {} at 0x{pc:04X}", instr.mnemonic()),
+        Event::Read { addr, value } => format!("Watchpoint {id}, 0x{pc:04X} read 0x{value:02X} from 0x{addr:04X}"),
+        Event::Write { addr, value } => format!("Watchpoint {id}, 0x{pc:04X} wrote 0x{value:02X} to 0x{addr:04X}"),
+        Event::Register { reg, old, new } => format!("Watchpoint {id}, 0x{pc:04X} changed {reg} from 0x{old:02X} to 0x{new:02X}"),
+    }
+}
+
+//An address, a register or a mnemonic as printed by `disas`
+fn parse_breakpoint(arg: &str) -> Result<Breakpoint, String> {
+    if arg.starts_with(|c: char| c.is_ascii_digit()) {
+        return parse_addr(arg).map(Breakpoint::Exec);
+    }
+    if let Ok(reg) = arg.parse() {
+        return Ok(Breakpoint::Register(reg));
+    }
+    arg.parse()
+        .map(Breakpoint::Instr)
+        .map_err(|_| format!("Expected an address, a register or a mnemonic, found \"{arg}\""))
+}
+
+//0x-prefixed hex or decimal
+fn parse_num(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x").or(text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("Expected a number, found \"{text}\""))
+}
+
+fn parse_addr(text: &str) -> Result<u16, String> {
+    let addr = parse_num(text)?;
+    u16::try_from(addr).map_err(|_| format!("Address {text} is out of range, the largest is 0xFFFF"))
+}
+
+fn parse_range(text: &str) -> Result<RangeInclusive<u16>, String> {
+    let (start, end) = text.split_once('-').unwrap_or((text, text));
+    Ok(parse_addr(start)?..=parse_addr(end)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use chip8_decode::syntax::Syntax;
+
+    use super::*;
+    use chip8_hw::chip8::Platform;
+
+    //Run `input` through a fresh session and return everything it printed
+    fn run(rom: &[u8], input: &str) -> (Session, String) {
+        let mut session = Session::new(Chip8::load_rom(Platform::Modern, rom).unwrap(), Formatter::new(Syntax::Cowgod));
+        let mut out = Vec::new();
+        session.serve(input.as_bytes(), &mut out).unwrap();
+        (session, String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn commands() {
+        //CALL 0x206; JP 0x202; 0x0000; LD V0, 0x07; LD I, 0x300; LD [I], V0; RET
+        let rom = [0x22, 0x06, 0x12, 0x02, 0x00, 0x00, 0x60, 0x07, 0xA3, 0x00, 0xF0, 0x55, 0x00, 0xEE];
+
+        let (session, out) = run(&rom, "break DRW\nbreak 0x20A\nwatch 0x300\nbreak\ncontinue\nbt\n\nregs\nfinish\nx/2 0x300\n");
+        assert_eq!(session.c8.pc, 0x20C);
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines[..7], [
+            "Breakpoint 0, every DRW",
+            "Breakpoint 1, at 0x020A",
+            "Watchpoint 2, write 0x0300-0x0300",
+            "0  every DRW",
+            "1  at 0x020A",
+            "2  write 0x0300-0x0300",
+            "Breakpoint 1 at 0x020A",
+        ]);
+        assert_eq!(lines[7], "=> 0x020A  F055       LD [I], V0");
+        assert_eq!(lines[8..10], ["#0  0x020A", "#1  0x0200  returns to 0x0202"]);
+
+        //The empty line repeats bt
+        assert_eq!(lines[10..12], ["#0  0x020A", "#1  0x0200  returns to 0x0202"]);
+        assert_eq!(lines[12], "V0 = 0x07  V1 = 0x00  V2 = 0x00  V3 = 0x00");
+
+        //finish resumes from the breakpoint, then the watchpoint stops it
+        assert_eq!(lines[18..20], ["Watchpoint 2, 0x020A wrote 0x07 to 0x0300", "=> 0x020C  00EE       RET"]);
+        assert_eq!(lines.last(), Some(&"0x0300:  07 00"));
+    }
+
+    #[test]
+    fn errors() {
+        let (session, out) = run(&[], "break LDL\nx/4 0x10000\nset PC = 0x10000\nset V0 300\nfinish\nbogus\nquit\nregs\n");
+        assert_eq!(session.dbg.breakpoints().count(), 0);
+        assert_eq!(session.c8.pc, 0x200);
+        assert_eq!(out.lines().collect::<Vec<_>>(), [
+            "Expected an address, a register or a mnemonic, found \"LDL\".",
+            "Address 0x10000 is out of range, the largest is 0xFFFF.",
+            "Address 0x10000 is out of range, the largest is 0xFFFF.",
+            "300 doesn't fit in V0.",
+            "Not in a subroutine.",
+            "Unknown command \"bogus\", try help.",
+        ]);
+    }
+
+    #[test]
+    fn timers_tick_per_frame() {
+        //LD V0, 0x3C; LD DT, V0; ADD V1, 0x01; JP 0x204
+        let rom = [0x60, 0x3C, 0xF0, 0x15, 0x71, 0x01, 0x12, 0x04];
+
+        //However the instructions are split up, the timers tick once every frame's worth
+        let ipf = Platform::Modern.instructions_per_frame();
+        let (stepped, _) = run(&rom, &format!("step {}\nstep {}\n", ipf - 1, ipf + 1));
+        let (continued, _) = run(&rom, "continue 2\n");
+        assert_eq!(stepped.c8.timers.delay(), 0x3A);
+        assert_eq!(continued.c8.timers.delay(), 0x3A);
+    }
+}
\ No newline at end of file